@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy_easings::EasingComponent;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::components::{RunState, Settings};
+use crate::engine::{GameRng, Grid, Solver, SpawnTable, Strategy};
+use crate::{board_shift, game_reset, MoveRequestEvent, SWIPE_MIN_DISTANCE};
+
+/// How fast the game is played when it plays itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoplaySpeed {
+    MovesPerSecond(f32),
+    /// The next move is played once the tiles of the last one stopped.
+    AfterAnimation,
+}
+
+impl AutoplaySpeed {
+    /// Range of [`AutoplaySpeed::MovesPerSecond`].
+    pub const MIN_MOVES_PER_SECOND: f32 = 0.25;
+    pub const MAX_MOVES_PER_SECOND: f32 = 64.0;
+
+    fn faster(self) -> Self {
+        match self {
+            AutoplaySpeed::MovesPerSecond(speed) => {
+                AutoplaySpeed::MovesPerSecond((speed * 2.0).min(Self::MAX_MOVES_PER_SECOND))
+            }
+            AutoplaySpeed::AfterAnimation => self,
+        }
+    }
+
+    fn slower(self) -> Self {
+        match self {
+            AutoplaySpeed::MovesPerSecond(speed) => {
+                AutoplaySpeed::MovesPerSecond((speed / 2.0).max(Self::MIN_MOVES_PER_SECOND))
+            }
+            // back to a fixed pace, a bit slower than the animations
+            AutoplaySpeed::AfterAnimation => AutoplaySpeed::MovesPerSecond(8.0),
+        }
+    }
+}
+
+impl Default for AutoplaySpeed {
+    fn default() -> Self {
+        AutoplaySpeed::MovesPerSecond(4.0)
+    }
+}
+
+/// Whether a strategy is playing the running game.
+pub struct Autoplay {
+    pub enabled: bool,
+    pub speed: AutoplaySpeed,
+    pub strategy: Arc<dyn Strategy>,
+    /// What the strategy draws from, apart from the spawns.
+    rng: GameRng,
+    /// Seconds since startup of the last move played.
+    last_move: f64,
+}
+
+impl FromWorld for Autoplay {
+    fn from_world(world: &mut World) -> Self {
+        let settings = world.resource::<Settings>();
+        Self {
+            enabled: false,
+            speed: settings.autoplay_speed,
+            strategy: settings.autoplay_strategy.clone().unwrap_or_else(|| {
+                let solver = Solver::new(3)
+                    .with_time_budget(Duration::from_millis(10))
+                    .with_spawns(settings.spawns_per_move);
+                Arc::new(solver)
+            }),
+            rng: strategy_rng(world.resource::<GameRng>()),
+            last_move: 0.0,
+        }
+    }
+}
+
+/// Starts or stops autoplay.
+pub struct ToggleAutoplayEvent;
+
+/// Changes how fast autoplay plays.
+pub enum AutoplaySpeedEvent {
+    Faster,
+    Slower,
+}
+
+// keys changing the speed, which don't stop autoplay
+const SPEED_KEYS: [KeyCode; 5] = [
+    KeyCode::Equals,
+    KeyCode::NumpadAdd,
+    KeyCode::Minus,
+    KeyCode::NumpadSubtract,
+    KeyCode::Key0,
+];
+
+pub struct AutoplayPlugin;
+
+impl Plugin for AutoplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Autoplay>()
+            .add_event::<ToggleAutoplayEvent>()
+            .add_event::<AutoplaySpeedEvent>()
+            .add_system_set(
+                SystemSet::on_update(RunState::Playing)
+                    .with_system(autoplay_input)
+                    .with_system(autoplay.after(autoplay_input).before(board_shift)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(RunState::Playing).with_system(seed_autoplay.after(game_reset)),
+            )
+            .add_system_set(SystemSet::on_enter(RunState::GameOver).with_system(stop_autoplay));
+    }
+}
+
+fn autoplay_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    touches: Res<Touches>,
+    mut toggle_reader: EventReader<ToggleAutoplayEvent>,
+    mut speed_reader: EventReader<AutoplaySpeedEvent>,
+    mut autoplay: ResMut<Autoplay>,
+) {
+    let speed = autoplay.speed;
+    // the buttons also set the speed before autoplay starts
+    for event in speed_reader.iter() {
+        autoplay.speed = match event {
+            AutoplaySpeedEvent::Faster => autoplay.speed.faster(),
+            AutoplaySpeedEvent::Slower => autoplay.speed.slower(),
+        };
+    }
+    if keyboard_input.just_pressed(KeyCode::P) || toggle_reader.iter().count() > 0 {
+        autoplay.enabled = !autoplay.enabled;
+        info!("autoplay {}", if autoplay.enabled { "on" } else { "off" });
+    } else if autoplay.enabled {
+        if keyboard_input.just_pressed(KeyCode::Equals)
+            || keyboard_input.just_pressed(KeyCode::NumpadAdd)
+        {
+            autoplay.speed = autoplay.speed.faster();
+        } else if keyboard_input.just_pressed(KeyCode::Minus)
+            || keyboard_input.just_pressed(KeyCode::NumpadSubtract)
+        {
+            autoplay.speed = autoplay.speed.slower();
+        } else if keyboard_input.just_pressed(KeyCode::Key0) {
+            autoplay.speed = AutoplaySpeed::AfterAnimation;
+        } else if keyboard_input
+            .get_just_pressed()
+            .any(|key| !SPEED_KEYS.contains(key))
+            || touches
+                .iter_just_released()
+                .any(|touch| touch.distance().length() >= SWIPE_MIN_DISTANCE)
+        {
+            // the player takes over
+            autoplay.enabled = false;
+            info!("autoplay off");
+        }
+    }
+    if autoplay.speed != speed {
+        info!("autoplay speed {:?}", autoplay.speed);
+    }
+}
+
+fn autoplay(
+    mut autoplay: ResMut<Autoplay>,
+    grid: Res<Grid>,
+    spawn_table: Res<SpawnTable>,
+    time: Res<Time>,
+    animations: Query<(), With<EasingComponent<Transform>>>,
+    mut move_writer: EventWriter<MoveRequestEvent>,
+) {
+    if !autoplay.enabled {
+        return;
+    }
+    let now = time.seconds_since_startup();
+    let ready = match autoplay.speed {
+        AutoplaySpeed::MovesPerSecond(speed) => now - autoplay.last_move >= 1.0 / f64::from(speed),
+        AutoplaySpeed::AfterAnimation => animations.is_empty(),
+    };
+    if !ready {
+        return;
+    }
+    let autoplay = &mut *autoplay;
+    let direction = autoplay
+        .strategy
+        .next_move(&grid, &spawn_table, &mut autoplay.rng);
+    if let Some(direction) = direction {
+        move_writer.send(MoveRequestEvent(direction));
+        autoplay.last_move = now;
+    }
+}
+
+// seeded from the game like in the simulator, so the tiles spawned only
+// depend on the moves, and a seeded game autoplays the same every time
+fn strategy_rng(game_rng: &GameRng) -> GameRng {
+    GameRng::new(!game_rng.seed())
+}
+
+fn seed_autoplay(mut autoplay: ResMut<Autoplay>, game_rng: Res<GameRng>) {
+    autoplay.rng = strategy_rng(&game_rng);
+}
+
+fn stop_autoplay(mut autoplay: ResMut<Autoplay>) {
+    autoplay.enabled = false;
+}
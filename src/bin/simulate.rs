@@ -0,0 +1,354 @@
+//! Plays many games without a window and reports how a strategy fares.
+//!
+//! ```text
+//! cargo run --release --bin simulate -- --strategy expectimax --seeds 0..1000 --size 4x4
+//! ```
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use yars2048::engine::{
+    strategy_from_name, GameRng, Grid, Rules, Ruleset, Solver, SpawnTable, Strategy,
+};
+use yars2048::Settings;
+
+const USAGE: &str = "\
+usage: simulate [options]
+  --strategy NAME   random, corner, greedy or expectimax (default expectimax)
+  --depth N         moves looked ahead by expectimax (default 2)
+  --seeds A..B      seeds of the games, one game per seed (default 0..100)
+  --size WxH        board size, or N for a square board, 3 to 8 (default 4x4)
+  --hex SIDE        hex board with SIDE tiles on a side, 2 to 5
+  --torus           board wrapping around its edges
+  --rules NAME      classic, fibonacci, threes or powers-of-three
+  --opening-tiles N tiles on the board when a game starts (default 2)
+  --spawns N        tiles spawned after every move (default 1)
+  --threads N       games played at once (default: one per core)
+  --json            print the report as JSON instead of a table";
+
+#[derive(Debug)]
+struct Options {
+    strategy: Box<dyn Strategy>,
+    seeds: Range<u64>,
+    board: Grid,
+    spawn_table: SpawnTable,
+    opening_tiles: u32,
+    spawns_per_move: u32,
+    threads: usize,
+    json: bool,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut strategy = "expectimax".to_string();
+        let mut depth = 2;
+        let mut seeds = 0..100;
+        let (mut width, mut height) = (4, 4);
+        let mut hex = None;
+        let mut torus = false;
+        let mut rules = Rules::Classic;
+        let mut opening_tiles = 2;
+        let mut spawns_per_move = 1;
+        let mut threads = thread::available_parallelism().map_or(1, usize::from);
+        let mut json = false;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |what: &str| {
+                args.next()
+                    .unwrap_or_else(|| panic!("{} expects {}\n{}", arg, what, USAGE))
+            };
+            match arg.as_str() {
+                "--strategy" => strategy = value("a strategy"),
+                "--depth" => depth = value("a number").parse().expect("--depth expects a number"),
+                "--seeds" => {
+                    let range = value("a range of seeds");
+                    seeds = range
+                        .split_once("..")
+                        .and_then(|(start, end)| Some(start.parse().ok()?..end.parse().ok()?))
+                        .expect("--seeds expects a range like 0..1000");
+                }
+                "--size" => {
+                    let size = value("a board size");
+                    (width, height) = match size.split_once('x') {
+                        Some((width, height)) => width.parse().ok().zip(height.parse().ok()),
+                        None => size.parse().ok().map(|side| (side, side)),
+                    }
+                    .expect("--size expects a size like 4x4");
+                }
+                "--hex" => hex = Some(value("a side").parse().expect("--hex expects a number")),
+                "--torus" => torus = true,
+                "--rules" => {
+                    let name = value("a rule set");
+                    rules = Rules::from_name(&name)
+                        .unwrap_or_else(|| panic!("unknown rule set {}", name));
+                }
+                "--opening-tiles" => {
+                    opening_tiles = value("a number")
+                        .parse()
+                        .expect("--opening-tiles expects a number");
+                }
+                "--spawns" => {
+                    spawns_per_move = value("a number")
+                        .parse()
+                        .expect("--spawns expects a number");
+                }
+                "--threads" => {
+                    threads = value("a number")
+                        .parse()
+                        .expect("--threads expects a number");
+                }
+                "--json" => json = true,
+                "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => panic!("unknown argument {}\n{}", arg, USAGE),
+            }
+        }
+
+        // the boards the game can show
+        let sizes = Settings::BOARD_SIZES;
+        if !sizes.contains(&width) || !sizes.contains(&height) {
+            panic!(
+                "--size expects sizes from {} to {}\n{}",
+                sizes.start(),
+                sizes.end(),
+                USAGE
+            );
+        }
+        let sides = Settings::HEX_SIDES;
+        if matches!(hex, Some(side) if !sides.contains(&side)) {
+            panic!(
+                "--hex expects a side from {} to {}\n{}",
+                sides.start(),
+                sides.end(),
+                USAGE
+            );
+        }
+
+        let board = match (hex, torus) {
+            (Some(side), _) => Grid::hex(side),
+            (None, true) => Grid::torus(width, height),
+            (None, false) => Grid::new(width, height),
+        };
+        Self {
+            strategy: match strategy.as_str() {
+                "expectimax" => Box::new(Solver::new(depth).with_spawns(spawns_per_move)),
+                name => strategy_from_name(name)
+                    .unwrap_or_else(|| panic!("unknown strategy {}\n{}", name, USAGE)),
+            },
+            seeds,
+            board: board.with_ruleset(Arc::new(rules)),
+            spawn_table: rules.spawn_table(),
+            opening_tiles,
+            spawns_per_move,
+            threads: threads.max(1),
+            json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GameResult {
+    score: u64,
+    max_level: Option<u32>,
+    moves: u32,
+}
+
+fn play(options: &Options, seed: u64) -> GameResult {
+    let mut grid = options.board.clone();
+    let mut rng = GameRng::new(seed);
+    // the strategy draws from its own generator, so the tiles spawned only
+    // depend on the moves
+    let mut strategy_rng = GameRng::new(!seed);
+    for _ in 0..options.opening_tiles {
+        grid.spawn(&options.spawn_table, &mut rng);
+    }
+    let mut result = GameResult {
+        score: 0,
+        max_level: None,
+        moves: 0,
+    };
+    while let Some(direction) =
+        options
+            .strategy
+            .next_move(&grid, &options.spawn_table, &mut strategy_rng)
+    {
+        let outcome = grid
+            .play_with_spawns(
+                direction,
+                &options.spawn_table,
+                options.spawns_per_move,
+                &mut rng,
+            )
+            .expect("strategies pick moves that move tiles");
+        result.score = result.score.saturating_add(outcome.score);
+        result.moves += 1;
+    }
+    result.max_level = grid.max_level();
+    result
+}
+
+// plays every seed, spread over the threads
+fn simulate(options: &Options) -> Vec<GameResult> {
+    let seeds = options.seeds.clone().collect::<Vec<_>>();
+    let chunk_size = seeds.len().div_ceil(options.threads).max(1);
+    thread::scope(|scope| {
+        let workers = seeds
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|seed| play(options, *seed))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a simulation thread panicked"))
+            .collect()
+    })
+}
+
+struct Report {
+    games: usize,
+    elapsed: Duration,
+    // sorted
+    scores: Vec<u64>,
+    mean_score: f64,
+    mean_moves: f64,
+    total_moves: u64,
+    // level and number of games that ended with it as the highest tile
+    max_levels: Vec<(Option<u32>, usize)>,
+}
+
+impl Report {
+    fn new(results: &[GameResult], elapsed: Duration) -> Self {
+        let games = results.len();
+        let mut scores = results
+            .iter()
+            .map(|result| result.score)
+            .collect::<Vec<_>>();
+        scores.sort_unstable();
+        let total_moves = results.iter().map(|result| u64::from(result.moves)).sum();
+        let mut max_levels = Vec::<(Option<u32>, usize)>::new();
+        for result in results {
+            match max_levels
+                .iter_mut()
+                .find(|(level, _)| *level == result.max_level)
+            {
+                Some((_, count)) => *count += 1,
+                None => max_levels.push((result.max_level, 1)),
+            }
+        }
+        max_levels.sort_unstable();
+        let games_f64 = games.max(1) as f64;
+        Self {
+            games,
+            elapsed,
+            mean_score: scores.iter().map(|score| *score as f64).sum::<f64>() / games_f64,
+            scores,
+            mean_moves: total_moves as f64 / games_f64,
+            total_moves,
+            max_levels,
+        }
+    }
+
+    // the score that `percent` of the games didn't beat
+    fn percentile(&self, percent: usize) -> u64 {
+        match self.scores.len() {
+            0 => 0,
+            len => self.scores[(len - 1) * percent / 100],
+        }
+    }
+
+    fn games_per_second(&self) -> f64 {
+        self.games as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn moves_per_second(&self) -> f64 {
+        self.total_moves as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn label(ruleset: &dyn Ruleset, level: Option<u32>) -> String {
+        level.map_or_else(|| "-".to_string(), |level| ruleset.label(level))
+    }
+
+    fn print_table(&self, ruleset: &dyn Ruleset) {
+        println!("games          {}", self.games);
+        println!("average moves  {:.1}", self.mean_moves);
+        println!(
+            "throughput     {:.1} games/s, {:.0} moves/s ({:.2?})",
+            self.games_per_second(),
+            self.moves_per_second(),
+            self.elapsed
+        );
+        println!();
+        println!("score   min {:>8}", self.percentile(0));
+        for percent in [25, 50, 75, 90] {
+            println!("        p{:<2} {:>8}", percent, self.percentile(percent));
+        }
+        println!("        max {:>8}", self.percentile(100));
+        println!("       mean {:>10.1}", self.mean_score);
+        println!();
+        println!("max tile     games      %");
+        for (level, count) in &self.max_levels {
+            println!(
+                "{:>8} {:>9} {:>6.1}",
+                Self::label(ruleset, *level),
+                count,
+                100.0 * *count as f64 / self.games as f64
+            );
+        }
+    }
+
+    fn print_json(&self, ruleset: &dyn Ruleset) {
+        let percentiles = [0, 25, 50, 75, 90, 100]
+            .iter()
+            .map(|percent| format!("\"p{}\": {}", percent, self.percentile(*percent)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let max_tiles = self
+            .max_levels
+            .iter()
+            .map(|(level, count)| {
+                format!(
+                    "{{\"level\": {}, \"tile\": \"{}\", \"games\": {}}}",
+                    level.map_or_else(|| "null".to_string(), |level| level.to_string()),
+                    Self::label(ruleset, *level),
+                    count
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("{{");
+        println!("  \"games\": {},", self.games);
+        println!("  \"average_moves\": {:.3},", self.mean_moves);
+        println!("  \"seconds\": {:.3},", self.elapsed.as_secs_f64());
+        println!("  \"games_per_second\": {:.3},", self.games_per_second());
+        println!("  \"moves_per_second\": {:.3},", self.moves_per_second());
+        println!(
+            "  \"score\": {{\"mean\": {:.3}, {}}},",
+            self.mean_score, percentiles
+        );
+        println!("  \"max_tiles\": [{}]", max_tiles);
+        println!("}}");
+    }
+}
+
+fn main() {
+    let options = Options::parse();
+    let start = Instant::now();
+    let results = simulate(&options);
+    let report = Report::new(&results, start.elapsed());
+    if options.json {
+        report.print_json(options.board.ruleset());
+    } else {
+        report.print_table(options.board.ruleset());
+    }
+}
@@ -1,22 +1,63 @@
 use bevy::prelude::*;
 
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::autoplay::AutoplaySpeed;
+use crate::engine::{Cell, Grid, Rules, Ruleset, SpawnTable, Strategy, Tile, TileKind, Topology};
+
+/// A cell of the board, in axial coordinates on hex boards (see
+/// [`Topology::Hex`]).
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Component)]
 pub struct Position {
     pub x: u8,
     pub y: u8,
 }
 
+impl From<Cell> for Position {
+    fn from(cell: Cell) -> Self {
+        Self {
+            x: cell.x,
+            y: cell.y,
+        }
+    }
+}
+
+impl From<Position> for Cell {
+    fn from(pos: Position) -> Self {
+        Self { x: pos.x, y: pos.y }
+    }
+}
+
 #[derive(Debug, Component)]
 pub struct Block {
+    pub kind: TileKind,
     pub level: u32,
 }
 
+impl From<Tile> for Block {
+    fn from(tile: Tile) -> Self {
+        Self {
+            kind: tile.kind,
+            level: tile.level,
+        }
+    }
+}
+
 impl Block {
-    /// Each level has a unique color (up to 9).
+    /// Each level has a unique color.
     /// Returns the color for a given tile.
     /// from https://github.com/tpcstld/2048/tree/master/2048/base/src/main/res/drawable-mdpi
-    pub fn color(&self) -> Color {
-        match self.level {
+    /// up to 12, then dark colors going around the color wheel.
+    /// The ruleset may shift levels along these colors or replace them.
+    pub fn color(&self, ruleset: &dyn Ruleset) -> Color {
+        if self.kind == TileKind::Joker {
+            return Color::rgb_u8(142, 68, 173);
+        }
+        if let Some([r, g, b]) = ruleset.color(self.level) {
+            return Color::rgb_u8(r, g, b);
+        }
+        match ruleset.shade(self.level) {
             1 => Color::rgb_u8(238, 228, 218),
             2 => Color::rgb_u8(237, 224, 200),
             3 => Color::rgb_u8(242, 177, 121),
@@ -29,19 +70,44 @@ impl Block {
             10 => Color::rgb_u8(237, 197, 63),
             11 => Color::rgb_u8(237, 194, 46),
             12 => Color::rgb_u8(60, 58, 50),
-            _ => Color::BLACK,
+            level => Color::hsl(level.saturating_sub(13) as f32 * 47.0 % 360.0, 0.5, 0.3),
         }
     }
 
-    /// Calculates the score of a given tile (pow(2, level)).
-    pub fn score(&self) -> u32 {
-        2u32.pow(self.level)
+    /// Color of the number, readable on top of [`Block::color`].
+    pub fn text_color(&self, ruleset: &dyn Ruleset) -> Color {
+        if self.kind == TileKind::Number
+            && ruleset.color(self.level).is_none()
+            && ruleset.shade(self.level) < 12
+        {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        }
+    }
+
+    /// The number shown on the tile, see [`Ruleset::label`].
+    pub fn label(&self, ruleset: &dyn Ruleset) -> String {
+        match self.kind {
+            TileKind::Number => ruleset.label(self.level),
+            TileKind::Joker => "\u{d7}2".to_string(),
+        }
+    }
+
+    /// Font size that fits the label in a tile.
+    pub fn font_size(&self, ruleset: &dyn Ruleset) -> f32 {
+        match self.label(ruleset).len() {
+            0..=2 => 40.0,
+            3 => 32.0,
+            4 => 26.0,
+            _ => 20.0,
+        }
     }
 }
 
 impl Default for Block {
     fn default() -> Self {
-        Self { level: 1 }
+        Tile::number(1).into()
     }
 }
 
@@ -51,21 +117,134 @@ pub struct BlockText;
 #[derive(Component)]
 pub struct EmptyBlock;
 
+/// A cell of the board that is blocked for the whole game.
+#[derive(Component)]
+pub struct Wall;
+
 #[derive(Component)]
 pub struct Board {
-    pub size: u8,
+    pub width: u8,
+    pub height: u8,
+    /// Only cells of the board.
+    pub walls: Vec<Cell>,
+    pub ruleset: Arc<dyn Ruleset>,
+    pub topology: Topology,
+    /// Shape of the tiles, plain squares when `None`.
+    pub tile_texture: Option<Handle<Image>>,
+}
+
+impl Board {
+    /// An empty grid for a new game on this board.
+    pub fn grid(&self) -> Grid {
+        let mut grid = match self.topology {
+            Topology::Square => Grid::new(self.width, self.height),
+            Topology::Hex => Grid::hex(self.width / 2 + 1),
+            Topology::Torus => Grid::torus(self.width, self.height),
+        };
+        for wall in &self.walls {
+            grid.set_wall(*wall, true);
+        }
+        grid.with_ruleset(self.ruleset.clone())
+    }
+}
+
+/// Options for a game, chosen before the app starts.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Number of columns of the board, clamped to [`Settings::BOARD_SIZES`].
+    /// On hex boards, the number of tiles on a side, clamped to
+    /// [`Settings::HEX_SIDES`].
+    pub board_width: u8,
+    /// Number of rows of the board, clamped to [`Settings::BOARD_SIZES`].
+    /// Unused on hex boards.
+    pub board_height: u8,
+    pub topology: Topology,
+    /// How tiles merge and score, [`Rules::Classic`] by default.
+    /// [`Settings::with_ruleset`] also picks its spawn table and win level.
+    pub ruleset: Arc<dyn Ruleset>,
+    /// Spawned tiles and their odds, inserted as a resource when the app
+    /// starts so it can be swapped for harder or easier variants.
+    pub spawn_table: SpawnTable,
+    /// Number of tiles on the board when a game starts.
+    pub opening_tiles: u32,
+    /// Number of tiles spawned after every move, more makes the game harder.
+    pub spawns_per_move: u32,
+    /// Seed of the random number generator, a random one is picked for
+    /// every game when `None`.
+    pub seed: Option<u64>,
+    /// Level of the tile that wins the game, 11 being the 2048 tile.
+    pub win_level: u32,
+    /// Cells blocked for the whole game, ignored when outside of the board.
+    pub walls: Vec<Cell>,
+    /// Number of moves that can be undone in a game, unlimited when `None`.
+    pub undo_limit: Option<u32>,
+    /// How fast autoplay starts playing, it can be changed while it plays.
+    pub autoplay_speed: AutoplaySpeed,
+    /// Who plays during autoplay. When `None`, expectimax thinking up to
+    /// 10ms per move and expecting [`Settings::spawns_per_move`] spawns.
+    pub autoplay_strategy: Option<Arc<dyn Strategy>>,
+    /// Share code of a game to load in place of the first one, see
+    /// [`SharedGame`](crate::engine::SharedGame).
+    pub load_code: Option<String>,
+}
+
+impl Settings {
+    pub const BOARD_SIZES: RangeInclusive<u8> = 3..=8;
+    pub const HEX_SIDES: RangeInclusive<u8> = 2..=5;
+
+    /// Plays by `ruleset`, with its spawn table and win level.
+    pub fn with_ruleset(mut self, ruleset: impl Ruleset + 'static) -> Self {
+        self.spawn_table = ruleset.spawn_table();
+        self.win_level = ruleset.win_level();
+        self.ruleset = Arc::new(ruleset);
+        self
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            board_width: 4,
+            board_height: 4,
+            topology: Topology::Square,
+            ruleset: Arc::new(Rules::Classic),
+            spawn_table: SpawnTable::default(),
+            opening_tiles: 2,
+            spawns_per_move: 1,
+            seed: None,
+            win_level: 11,
+            walls: Vec::new(),
+            undo_limit: None,
+            autoplay_speed: AutoplaySpeed::default(),
+            autoplay_strategy: None,
+            load_code: None,
+        }
+    }
 }
 
 #[derive(Default, Component)]
 pub struct Game {
-    pub score: u32,
-    pub score_best: u32,
+    pub score: u64,
+    pub score_best: u64,
+    /// Whether the win level was reached, the player may keep going after.
+    pub won: bool,
+    pub moves: u32,
+    /// Hints shown in this game.
+    pub hints: u32,
+    /// Seconds since startup when the game began.
+    pub started: f64,
 }
 
+/// `Won`, `GameOver` and `EnteringCode` are pushed on top of `Playing`, so
+/// popping them resumes the game without resetting the board. A new game
+/// replaces the whole stack with `Playing`.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Component)]
 pub enum RunState {
     Playing,
+    Won,
     GameOver,
+    /// Typing a share code to load, moves are paused.
+    EnteringCode,
 }
 
 #[derive(Component)]
@@ -73,3 +252,11 @@ pub struct ScoreDisplay;
 
 #[derive(Component)]
 pub struct BestScoreDisplay;
+
+#[derive(Component)]
+pub struct SeedDisplay;
+
+/// Shows the share code of the game, the code being typed or why it
+/// couldn't be loaded.
+#[derive(Component)]
+pub struct ShareCodeDisplay;
@@ -0,0 +1,998 @@
+//! The rules of the game, independent of Bevy.
+//!
+//! A [`Grid`] holds every [`Tile`] on the board and knows how to
+//! shift and spawn them. Each move is reported as a [`MoveOutcome`], which
+//! the ECS systems in `lib.rs` only mirror onto entities, so the same rules
+//! can be driven from plain code (tests, bots, tools) without an `App`.
+
+use rand::prelude::*;
+
+use std::sync::Arc;
+
+mod bitboard;
+mod rules;
+mod share;
+mod solver;
+mod strategy;
+
+pub use bitboard::BitBoard;
+pub use rules::{Rules, Ruleset};
+pub use share::{ShareCodeError, SharedGame};
+pub use solver::{MoveEvaluation, Solver};
+pub use strategy::{strategy_from_name, Corner, Greedy, RandomMoves, Strategy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+    // hex boards only
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    /// The directions of a square board.
+    pub const ALL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+    ];
+
+    /// The directions of a hex board, clockwise from the top.
+    pub const HEX: [Direction; 6] = [
+        Direction::Up,
+        Direction::UpRight,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::UpLeft,
+    ];
+}
+
+/// How the cells of a board connect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// Rows and columns.
+    #[default]
+    Square,
+    /// Flat topped hexagons in axial coordinates: `x` grows up and to the
+    /// right, `y` grows straight up. The board is a big hexagon, two corners
+    /// of its bounding rectangle aren't part of it.
+    Hex,
+    /// Rows and columns wrapping around, tiles leaving one side come back on
+    /// the other and merge across the seam.
+    ///
+    /// A wrapped line has no edge for the tiles to move towards, so it is
+    /// cut at its first wall, or else at the edge like a square board, unless
+    /// only tiles across the seam can merge. Every move of a square board is
+    /// then a move of the torus too.
+    Torus,
+}
+
+impl Topology {
+    /// The directions tiles can move in.
+    pub fn directions(self) -> &'static [Direction] {
+        match self {
+            Topology::Square | Topology::Torus => &Direction::ALL,
+            Topology::Hex => &Direction::HEX,
+        }
+    }
+
+    /// The `(x, y)` offset to the next cell in `direction`, `None` if tiles
+    /// can't move that way.
+    pub fn step(self, direction: Direction) -> Option<(i8, i8)> {
+        match (self, direction) {
+            (_, Direction::Up) => Some((0, 1)),
+            (_, Direction::Down) => Some((0, -1)),
+            (Topology::Square | Topology::Torus, Direction::Left) => Some((-1, 0)),
+            (Topology::Square | Topology::Torus, Direction::Right) => Some((1, 0)),
+            (Topology::Hex, Direction::UpRight) => Some((1, 0)),
+            (Topology::Hex, Direction::DownLeft) => Some((-1, 0)),
+            (Topology::Hex, Direction::DownRight) => Some((1, -1)),
+            (Topology::Hex, Direction::UpLeft) => Some((-1, 1)),
+            _ => None,
+        }
+    }
+}
+
+/// A cell on the board, `(0, 0)` being the bottom left corner.
+/// Coordinates are axial on hex boards, see [`Topology::Hex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub x: u8,
+    pub y: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileKind {
+    /// A tile whose level is given a value by the [`Ruleset`].
+    Number,
+    /// A wildcard merging with any numbered tile, which grows as if it
+    /// merged with its match.
+    Joker,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub kind: TileKind,
+    /// Always 0 for jokers.
+    pub level: u32,
+}
+
+impl Tile {
+    pub const JOKER: Tile = Tile {
+        kind: TileKind::Joker,
+        level: 0,
+    };
+
+    pub fn number(level: u32) -> Self {
+        Self {
+            kind: TileKind::Number,
+            level,
+        }
+    }
+}
+
+/// A tile that moved from one cell to another without merging.
+/// Tiles that stayed in place are reported with `from == to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slide {
+    pub from: Cell,
+    pub to: Cell,
+}
+
+/// Tiles that merged into one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    /// The cells the tiles came from, ordered from the edge the tiles moved
+    /// towards, so the first one is the tile that stays.
+    pub from: Vec<Cell>,
+    pub into: Cell,
+    /// The merged tile.
+    pub tile: Tile,
+}
+
+/// A tile put on the board after a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spawn {
+    pub cell: Cell,
+    pub tile: Tile,
+}
+
+/// The tiles new tiles are spawned as, and how likely each one is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnTable {
+    // (tile, weight)
+    weights: Vec<(Tile, u32)>,
+}
+
+impl SpawnTable {
+    /// Creates a table from `(level, weight)` pairs, a level being picked
+    /// with a probability of its weight over the sum of all weights.
+    ///
+    /// Panics if no level has a positive weight.
+    pub fn new(weights: Vec<(u32, u32)>) -> Self {
+        assert!(
+            weights.iter().any(|(_, weight)| *weight > 0),
+            "a spawn table needs at least one level with a positive weight"
+        );
+        Self {
+            weights: weights
+                .into_iter()
+                .map(|(level, weight)| (Tile::number(level), weight))
+                .collect(),
+        }
+    }
+
+    /// Adds jokers to the table, with `weight` relative to the levels.
+    pub fn with_jokers(mut self, weight: u32) -> Self {
+        self.weights
+            .retain(|(tile, _)| tile.kind != TileKind::Joker);
+        self.weights.push((Tile::JOKER, weight));
+        self
+    }
+
+    pub fn weights(&self) -> &[(Tile, u32)] {
+        &self.weights
+    }
+
+    /// Picks a new tile.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Tile {
+        self.weights
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .expect("a spawn table always has a positive weight")
+            .0
+    }
+}
+
+impl Default for SpawnTable {
+    /// The classic odds: 90% for a 2, 10% for a 4.
+    fn default() -> Self {
+        Self::new(vec![(1, 9), (2, 1)])
+    }
+}
+
+/// The source of all randomness of a game.
+///
+/// This is SplitMix64: every value is derived from the seed and the number of
+/// values drawn so far, so the same seed always gives the same game and the
+/// whole state is two numbers that are cheap to save and restore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameRng {
+    seed: u64,
+    draws: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self::with_draws(seed, 0)
+    }
+
+    /// Restores a generator that already drew `draws` values.
+    pub fn with_draws(seed: u64, draws: u64) -> Self {
+        Self { seed, draws }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws = self.draws.wrapping_add(1);
+        let mut z = self
+            .seed
+            .wrapping_add(self.draws.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Everything that happened to the board during a single move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveOutcome {
+    pub direction: Direction,
+    /// Every tile that didn't take part in a merge.
+    pub slides: Vec<Slide>,
+    pub merges: Vec<Merge>,
+    /// Points gained by the merges of this move.
+    pub score: u64,
+    /// The tiles put on the board once the others stopped moving, fewer
+    /// than asked for when the board filled up.
+    pub spawned: Vec<Spawn>,
+}
+
+impl MoveOutcome {
+    /// Whether any tile slid or merged.
+    pub fn moved(&self) -> bool {
+        !self.merges.is_empty() || self.slides.iter().any(|slide| slide.from != slide.to)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Grid {
+    width: u8,
+    height: u8,
+    // tiles, row by row starting at the bottom
+    tiles: Vec<Option<Tile>>,
+    // cells blocked for good, in the same order
+    walls: Vec<bool>,
+    ruleset: Arc<dyn Ruleset>,
+    topology: Topology,
+}
+
+/// Grids are equal when their rulesets have the same name.
+impl PartialEq for Grid {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.tiles == other.tiles
+            && self.walls == other.walls
+            && self.ruleset.name() == other.ruleset.name()
+            && self.topology == other.topology
+    }
+}
+
+impl Eq for Grid {}
+
+impl Grid {
+    /// Creates an empty board `width` tiles wide and `height` tiles high,
+    /// playing by the [`Rules::Classic`] rules.
+    pub fn new(width: u8, height: u8) -> Self {
+        let cells = usize::from(width) * usize::from(height);
+        Self {
+            width,
+            height,
+            tiles: vec![None; cells],
+            walls: vec![false; cells],
+            ruleset: Arc::new(Rules::Classic),
+            topology: Topology::Square,
+        }
+    }
+
+    /// Creates an empty board wrapping around, see [`Topology::Torus`].
+    pub fn torus(width: u8, height: u8) -> Self {
+        Self {
+            topology: Topology::Torus,
+            ..Self::new(width, height)
+        }
+    }
+
+    /// Creates an empty hexagonal board with `side` tiles on each side.
+    ///
+    /// Panics if `side` is 0 or above 128, the board being `2 * side - 1`
+    /// tiles across.
+    pub fn hex(side: u8) -> Self {
+        assert!(
+            (1..=128).contains(&side),
+            "no hex board has {} tiles on a side",
+            side
+        );
+        let size = 2 * (side - 1) + 1;
+        Self {
+            topology: Topology::Hex,
+            ..Self::new(size, size)
+        }
+    }
+
+    /// Creates an empty board with `walls` on it.
+    /// Walls outside of the board are ignored.
+    pub fn with_walls(width: u8, height: u8, walls: &[Cell]) -> Self {
+        let mut grid = Self::new(width, height);
+        for wall in walls {
+            if wall.x < width && wall.y < height {
+                grid.set_wall(*wall, true);
+            }
+        }
+        grid
+    }
+
+    /// Plays by `ruleset` instead.
+    pub fn with_ruleset(mut self, ruleset: Arc<dyn Ruleset>) -> Self {
+        self.ruleset = ruleset;
+        self
+    }
+
+    pub fn ruleset(&self) -> &dyn Ruleset {
+        &*self.ruleset
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Whether `cell` is part of the board.
+    pub fn contains(&self, cell: Cell) -> bool {
+        let in_bounds = cell.x < self.width && cell.y < self.height;
+        match self.topology {
+            Topology::Square | Topology::Torus => in_bounds,
+            Topology::Hex => {
+                // distance to the center of at most the radius
+                let radius = u16::from(self.width / 2);
+                let sum = u16::from(cell.x) + u16::from(cell.y);
+                in_bounds && (radius..=3 * radius).contains(&sum)
+            }
+        }
+    }
+
+    // the cell next to `cell` when moving by `step`, if it's on the board
+    fn neighbour(&self, cell: Cell, (dx, dy): (i8, i8)) -> Option<Cell> {
+        let next = Cell {
+            x: cell.x.checked_add_signed(dx)?,
+            y: cell.y.checked_add_signed(dy)?,
+        };
+        self.contains(next).then_some(next)
+    }
+
+    fn index(&self, cell: Cell) -> usize {
+        assert!(
+            cell.x < self.width && cell.y < self.height,
+            "cell {:?} is outside the board",
+            cell
+        );
+        usize::from(cell.y) * usize::from(self.width) + usize::from(cell.x)
+    }
+
+    /// Returns the tile at `cell`, if any.
+    pub fn get(&self, cell: Cell) -> Option<Tile> {
+        self.tiles[self.index(cell)]
+    }
+
+    pub fn set(&mut self, cell: Cell, tile: Option<Tile>) {
+        let index = self.index(cell);
+        self.tiles[index] = tile;
+    }
+
+    /// Whether `cell` is blocked: tiles stop against it and never spawn on it.
+    pub fn is_wall(&self, cell: Cell) -> bool {
+        self.walls[self.index(cell)]
+    }
+
+    /// Blocks or clears `cell`, removing any tile on it.
+    pub fn set_wall(&mut self, cell: Cell, wall: bool) {
+        let index = self.index(cell);
+        self.walls[index] = wall;
+        self.tiles[index] = None;
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.cells().filter(|cell| self.is_wall(*cell))
+    }
+
+    /// All cells of the board, row by row starting at the bottom.
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        let width = self.width;
+        (0..self.height)
+            .flat_map(move |y| (0..width).map(move |x| Cell { x, y }))
+            .filter(|cell| self.contains(*cell))
+    }
+
+    /// All tiles on the board with their cell.
+    pub fn tiles(&self) -> impl Iterator<Item = (Cell, Tile)> + '_ {
+        self.cells()
+            .filter_map(|cell| self.get(cell).map(|tile| (cell, tile)))
+    }
+
+    pub fn empty_cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.cells()
+            .filter(|cell| self.get(*cell).is_none() && !self.is_wall(*cell))
+    }
+
+    /// The lines tiles move along in `direction`, each ordered from the
+    /// edge the tiles move towards. None if tiles can't move that way.
+    /// Lines of a torus wrap from their last cell to their first one.
+    fn lines(&self, direction: Direction) -> Vec<Vec<Cell>> {
+        let (dx, dy) = match self.topology.step(direction) {
+            Some(step) => step,
+            None => return Vec::new(),
+        };
+        self.cells()
+            .filter(|cell| self.neighbour(*cell, (dx, dy)).is_none())
+            .map(|edge| {
+                std::iter::successors(Some(edge), |cell| self.neighbour(*cell, (-dx, -dy)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Slides every tile as far as possible in `direction`, merging
+    /// neighbours once per move (see [`Ruleset::merge`]). Walls split lines,
+    /// tiles on either side of a wall never meet.
+    /// The outcome doesn't contain spawned tiles, see [`Grid::play`].
+    /// A shift where nothing [`moved`](MoveOutcome::moved) leaves the board
+    /// untouched.
+    pub fn shift(&mut self, direction: Direction) -> MoveOutcome {
+        let mut outcome = MoveOutcome {
+            direction,
+            slides: Vec::new(),
+            merges: Vec::new(),
+            score: 0,
+            spawned: Vec::new(),
+        };
+
+        for mut line in self.lines(direction) {
+            if self.topology == Topology::Torus {
+                let start = self.ring_start(&line);
+                line.rotate_left(start);
+            }
+            let segments = line
+                .split(|cell| self.is_wall(*cell))
+                .map(|segment| segment.to_vec())
+                .collect::<Vec<_>>();
+            for segment in segments {
+                self.shift_segment(&segment, &mut outcome);
+            }
+        }
+
+        outcome
+    }
+
+    // where a wrapped line is cut, see `Topology::Torus`
+    fn ring_start(&self, ring: &[Cell]) -> usize {
+        if let Some(wall) = ring.iter().position(|cell| self.is_wall(*cell)) {
+            return wall;
+        }
+        // the tiles of the ring and where they are, empty cells don't keep
+        // tiles apart
+        let (positions, tiles): (Vec<_>, Vec<_>) = ring
+            .iter()
+            .enumerate()
+            .filter_map(|(index, cell)| self.get(*cell).map(|tile| (index, tile)))
+            .unzip();
+        let count = tiles.len();
+        let merge_size = self.ruleset.merge_size();
+        if count < merge_size {
+            return 0;
+        }
+        let merges_at = |start: usize| {
+            let group = (start..start + merge_size)
+                .map(|index| tiles[index % count])
+                .collect::<Vec<_>>();
+            self.ruleset.can_merge(&group)
+        };
+        if (0..=count - merge_size).any(merges_at) {
+            return 0;
+        }
+        (count + 1 - merge_size..count)
+            .find(|start| merges_at(*start))
+            .map_or(0, |start| positions[start])
+    }
+
+    // moves the tiles of a run of cells without walls towards its first cell
+    fn shift_segment(&mut self, segment: &[Cell], outcome: &mut MoveOutcome) {
+        let (cells, tiles): (Vec<_>, Vec<_>) = segment
+            .iter()
+            .filter_map(|cell| self.get(*cell).map(|tile| (*cell, tile)))
+            .unzip();
+        for cell in segment.iter() {
+            self.set(*cell, None);
+        }
+
+        let merge_size = self.ruleset.merge_size();
+        let mut target = segment.iter();
+        let mut next = 0;
+        while next < tiles.len() {
+            let to = *target
+                .next()
+                .expect("a segment has room for all of its tiles");
+            let merged = tiles
+                .get(next..next + merge_size)
+                .filter(|group| self.ruleset.can_merge(group))
+                .map(|group| self.ruleset.merge(group));
+            match merged {
+                Some(tile) => {
+                    let merge = Merge {
+                        from: cells[next..next + merge_size].to_vec(),
+                        into: to,
+                        tile,
+                    };
+                    next += merge_size;
+                    self.set(to, Some(merge.tile));
+                    outcome.score = outcome
+                        .score
+                        .saturating_add(self.ruleset.merge_score(merge.tile));
+                    outcome.merges.push(merge);
+                }
+                None => {
+                    self.set(to, Some(tiles[next]));
+                    outcome.slides.push(Slide {
+                        from: cells[next],
+                        to,
+                    });
+                    next += 1;
+                }
+            }
+        }
+    }
+
+    /// Whether shifting in `direction` would slide or merge any tile.
+    pub fn can_move(&self, direction: Direction) -> bool {
+        self.clone().shift(direction).moved()
+    }
+
+    /// Whether any move is left, the game is over otherwise.
+    pub fn has_moves(&self) -> bool {
+        self.topology
+            .directions()
+            .iter()
+            .any(|direction| self.can_move(*direction))
+    }
+
+    /// Level of the highest numbered tile on the board.
+    pub fn max_level(&self) -> Option<u32> {
+        self.tiles()
+            .filter(|(_, tile)| tile.kind == TileKind::Number)
+            .map(|(_, tile)| tile.level)
+            .max()
+    }
+
+    /// Shifts the tiles in `direction`, then puts a new tile on the board.
+    /// Returns `None` without spawning anything if no tile could move.
+    pub fn play<R: Rng + ?Sized>(
+        &mut self,
+        direction: Direction,
+        spawn_table: &SpawnTable,
+        rng: &mut R,
+    ) -> Option<MoveOutcome> {
+        self.play_with_spawns(direction, spawn_table, 1, rng)
+    }
+
+    /// Like [`Grid::play`], putting up to `spawns` new tiles on the board.
+    pub fn play_with_spawns<R: Rng + ?Sized>(
+        &mut self,
+        direction: Direction,
+        spawn_table: &SpawnTable,
+        spawns: u32,
+        rng: &mut R,
+    ) -> Option<MoveOutcome> {
+        let mut outcome = self.shift(direction);
+        if !outcome.moved() {
+            return None;
+        }
+        outcome.spawned = (0..spawns)
+            .map_while(|_| self.spawn(spawn_table, rng))
+            .collect();
+        Some(outcome)
+    }
+
+    /// Puts a new tile picked from `spawn_table` on a random empty cell.
+    /// Returns `None` if the board is full.
+    pub fn spawn<R: Rng + ?Sized>(
+        &mut self,
+        spawn_table: &SpawnTable,
+        rng: &mut R,
+    ) -> Option<Spawn> {
+        let cells = self.empty_cells().collect::<Vec<_>>();
+        let cell = *cells.choose(rng)?;
+        let spawn = Spawn {
+            cell,
+            tile: spawn_table.sample(rng),
+        };
+        self.set(cell, Some(spawn.tile));
+        Some(spawn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rows from the top, 0 for an empty cell and 99 for a joker
+    fn grid(rows: &[&[u32]]) -> Grid {
+        fill(Grid::new(rows[0].len() as u8, rows.len() as u8), rows)
+    }
+
+    fn fill(mut grid: Grid, rows: &[&[u32]]) -> Grid {
+        for (row, levels) in rows.iter().enumerate() {
+            let y = (rows.len() - 1 - row) as u8;
+            for (x, level) in levels.iter().enumerate() {
+                let tile = match level {
+                    0 => None,
+                    99 => Some(Tile::JOKER),
+                    level => Some(Tile::number(*level)),
+                };
+                grid.set(Cell { x: x as u8, y }, tile);
+            }
+        }
+        grid
+    }
+
+    fn ruled(rules: Rules, rows: &[&[u32]]) -> Grid {
+        let empty = Grid::new(rows[0].len() as u8, rows.len() as u8);
+        fill(empty.with_ruleset(Arc::new(rules)), rows)
+    }
+
+    fn torus(rows: &[&[u32]]) -> Grid {
+        fill(Grid::torus(rows[0].len() as u8, rows.len() as u8), rows)
+    }
+
+    #[test]
+    fn shift_merges_each_tile_once() {
+        let mut grid = grid(&[&[1, 1, 1, 1], &[0, 2, 0, 2], &[1, 0, 2, 1], &[3, 3, 3, 0]]);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(
+            grid,
+            self::grid(&[&[2, 2, 0, 0], &[3, 0, 0, 0], &[1, 2, 1, 0], &[4, 3, 0, 0]])
+        );
+        assert_eq!(outcome.score, 4 + 4 + 8 + 16);
+    }
+
+    #[test]
+    fn shift_in_every_direction() {
+        let mut grid = grid(&[&[1, 0, 0, 0], &[1, 0, 0, 0], &[0, 0, 0, 0], &[2, 0, 0, 1]]);
+        grid.shift(Direction::Up);
+        assert_eq!(
+            grid,
+            self::grid(&[&[2, 0, 0, 1], &[2, 0, 0, 0], &[0, 0, 0, 0], &[0, 0, 0, 0]])
+        );
+        grid.shift(Direction::Right);
+        assert_eq!(
+            grid,
+            self::grid(&[&[0, 0, 2, 1], &[0, 0, 0, 2], &[0, 0, 0, 0], &[0, 0, 0, 0]])
+        );
+        grid.shift(Direction::Down);
+        assert_eq!(
+            grid,
+            self::grid(&[&[0, 0, 0, 0], &[0, 0, 0, 0], &[0, 0, 0, 1], &[0, 0, 2, 2]])
+        );
+    }
+
+    #[test]
+    fn shift_rectangular_board() {
+        let mut grid = grid(&[&[1, 0, 1, 0, 0], &[0, 0, 0, 0, 2], &[1, 0, 0, 0, 2]]);
+        grid.shift(Direction::Right);
+        assert_eq!(
+            grid,
+            self::grid(&[&[0, 0, 0, 0, 2], &[0, 0, 0, 0, 2], &[0, 0, 0, 1, 2]])
+        );
+        grid.shift(Direction::Up);
+        assert_eq!(
+            grid,
+            self::grid(&[&[0, 0, 0, 1, 3], &[0, 0, 0, 0, 2], &[0, 0, 0, 0, 0]])
+        );
+    }
+
+    #[test]
+    fn outcome_records_slides_and_merges() {
+        let mut grid = grid(&[&[0, 0, 0, 0], &[0, 0, 0, 0], &[0, 0, 0, 0], &[1, 1, 0, 2]]);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(
+            outcome.merges,
+            vec![Merge {
+                from: vec![Cell { x: 0, y: 0 }, Cell { x: 1, y: 0 }],
+                into: Cell { x: 0, y: 0 },
+                tile: Tile::number(2),
+            }]
+        );
+        assert_eq!(
+            outcome.slides,
+            vec![Slide {
+                from: Cell { x: 3, y: 0 },
+                to: Cell { x: 1, y: 0 },
+            }]
+        );
+    }
+
+    #[test]
+    fn play_rejects_moves_changing_nothing() {
+        let mut grid = grid(&[&[0, 0, 0], &[0, 0, 0], &[1, 2, 0]]);
+        let before = grid.clone();
+        let spawn_table = SpawnTable::default();
+        let mut rng = GameRng::new(0);
+        assert!(grid.play(Direction::Left, &spawn_table, &mut rng).is_none());
+        assert!(grid.play(Direction::Down, &spawn_table, &mut rng).is_none());
+        assert_eq!(grid, before);
+        let outcome = grid.play(Direction::Up, &spawn_table, &mut rng).unwrap();
+        assert_eq!(outcome.spawned.len(), 1);
+        assert_eq!(grid.tiles().count(), 3);
+    }
+
+    #[test]
+    fn spawn_fills_the_board() {
+        let mut grid = Grid::new(3, 3);
+        let spawn_table = SpawnTable::default();
+        let mut rng = GameRng::new(0);
+        for _ in 0..9 {
+            assert!(grid.spawn(&spawn_table, &mut rng).is_some());
+        }
+        assert!(grid.spawn(&spawn_table, &mut rng).is_none());
+    }
+
+    #[test]
+    fn spawn_table_weights() {
+        let mut rng = GameRng::new(0);
+        let fours = (0..10_000)
+            .filter(|_| SpawnTable::default().sample(&mut rng) == Tile::number(2))
+            .count();
+        assert!((800..1200).contains(&fours), "{} fours", fours);
+        let threes = SpawnTable::new(vec![(1, 0), (3, 5)]);
+        assert!((0..100).all(|_| threes.sample(&mut rng) == Tile::number(3)));
+    }
+
+    #[test]
+    fn seeded_games_repeat() {
+        let play = |seed| {
+            let spawn_table = SpawnTable::default();
+            let mut rng = GameRng::new(seed);
+            let mut grid = Grid::new(4, 4);
+            grid.spawn(&spawn_table, &mut rng);
+            for direction in Direction::ALL.iter().cycle().take(40) {
+                grid.play(*direction, &spawn_table, &mut rng);
+            }
+            (grid, rng)
+        };
+        assert_eq!(play(7), play(7));
+        assert_ne!(play(7).0, play(8).0);
+        let (_, mut rng) = play(7);
+        let mut resumed = GameRng::with_draws(7, rng.draws());
+        assert_eq!(resumed.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn has_moves_on_full_boards() {
+        assert!(!grid(&[&[1, 2, 1], &[2, 1, 2], &[1, 2, 1]]).has_moves());
+        assert!(grid(&[&[1, 2, 1], &[2, 1, 2], &[1, 2, 2]]).has_moves());
+        assert!(grid(&[&[1, 2, 1], &[2, 1, 2], &[1, 2, 0]]).has_moves());
+        let grid = grid(&[&[1, 2, 3, 4], &[2, 3, 4, 1]]);
+        assert!(!grid.has_moves());
+        assert_eq!(grid.max_level(), Some(4));
+    }
+
+    #[test]
+    fn walls_block_tiles_and_spawns() {
+        let mut grid = grid(&[&[1, 0, 1, 1]]);
+        grid.set_wall(Cell { x: 1, y: 0 }, true);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(grid.get(Cell { x: 0, y: 0 }), Some(Tile::number(1)));
+        assert_eq!(grid.get(Cell { x: 2, y: 0 }), Some(Tile::number(2)));
+        assert_eq!(grid.get(Cell { x: 3, y: 0 }), None);
+        assert_eq!(outcome.score, 4);
+        assert!(!grid.can_move(Direction::Left));
+
+        let wall = Cell { x: 1, y: 1 };
+        let mut grid = Grid::with_walls(3, 3, &[wall, Cell { x: 9, y: 9 }]);
+        let mut rng = GameRng::new(1);
+        for _ in 0..8 {
+            assert!(grid.spawn(&SpawnTable::default(), &mut rng).is_some());
+        }
+        assert!(grid.spawn(&SpawnTable::default(), &mut rng).is_none());
+        assert!(grid.is_wall(wall));
+        assert_eq!(grid.get(wall), None);
+    }
+
+    #[test]
+    fn jokers_merge_with_numbers() {
+        let mut grid = grid(&[&[99, 3, 99, 99], &[0, 99, 0, 2]]);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(grid, self::grid(&[&[4, 99, 99, 0], &[3, 0, 0, 0]]));
+        assert_eq!(outcome.score, 16 + 8);
+        assert_eq!(grid.max_level(), Some(4));
+        let jokers = SpawnTable::new(vec![(1, 1)]).with_jokers(1_000_000);
+        assert_eq!(jokers.sample(&mut GameRng::new(3)), Tile::JOKER);
+    }
+
+    #[test]
+    fn rule_sets_merge() {
+        let mut grid = ruled(Rules::Fibonacci, &[&[1, 1, 2, 3], &[3, 5, 5, 0]]);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(
+            grid,
+            ruled(Rules::Fibonacci, &[&[2, 4, 0, 0], &[3, 5, 5, 0]])
+        );
+        assert_eq!(outcome.score, 2 + 5);
+
+        let mut grid = ruled(
+            Rules::Threes,
+            &[&[1, 2, 2, 2], &[3, 3, 1, 1], &[1, 99, 2, 0]],
+        );
+        grid.shift(Direction::Left);
+        assert_eq!(
+            grid,
+            ruled(
+                Rules::Threes,
+                &[&[3, 2, 2, 0], &[4, 1, 1, 0], &[3, 2, 0, 0]]
+            )
+        );
+
+        let rows: &[&[u32]] = &[&[1, 1, 1, 1], &[2, 2, 99, 0], &[1, 1, 0, 0]];
+        let mut grid = ruled(Rules::PowersOfThree, rows);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(
+            grid,
+            ruled(
+                Rules::PowersOfThree,
+                &[&[2, 1, 0, 0], &[3, 0, 0, 0], &[1, 1, 0, 0]]
+            )
+        );
+        assert_eq!(outcome.merges[0].from.len(), 3);
+        assert_eq!(outcome.score, 9 + 27);
+    }
+
+    #[test]
+    fn hex_board_lines() {
+        let mut grid = Grid::hex(3);
+        assert_eq!(grid.cells().count(), 19);
+        assert!(!grid.contains(Cell { x: 0, y: 0 }));
+        assert!(!grid.contains(Cell { x: 4, y: 4 }));
+        assert!(grid.contains(Cell { x: 0, y: 2 }));
+        assert!(grid.contains(Cell { x: 4, y: 0 }));
+        for cell in [
+            Cell { x: 0, y: 2 },
+            Cell { x: 2, y: 2 },
+            Cell { x: 3, y: 1 },
+        ] {
+            grid.set(cell, Some(Tile::number(1)));
+        }
+        let outcome = grid.shift(Direction::UpRight);
+        assert_eq!(outcome.merges.len(), 1);
+        assert_eq!(grid.get(Cell { x: 4, y: 2 }), Some(Tile::number(2)));
+        assert_eq!(grid.get(Cell { x: 4, y: 1 }), Some(Tile::number(1)));
+        assert!(!grid.can_move(Direction::Left));
+        assert!(!grid.can_move(Direction::DownRight));
+        grid.shift(Direction::DownLeft);
+        assert_eq!(grid.get(Cell { x: 0, y: 2 }), Some(Tile::number(2)));
+        assert_eq!(grid.get(Cell { x: 1, y: 1 }), Some(Tile::number(1)));
+        assert!(!grid.can_move(Direction::UpLeft));
+        grid.shift(Direction::Up);
+        assert_eq!(grid.get(Cell { x: 0, y: 4 }), Some(Tile::number(2)));
+        assert_eq!(grid.get(Cell { x: 1, y: 4 }), Some(Tile::number(1)));
+    }
+
+    #[test]
+    fn torus_moves_lines_with_gaps() {
+        let mut grid = torus(&[&[2, 4, 0, 8], &[0; 4], &[0; 4], &[0; 4]]);
+        assert!(grid.can_move(Direction::Left));
+        assert!(grid.can_move(Direction::Right));
+        grid.shift(Direction::Left);
+        assert_eq!(grid, torus(&[&[2, 4, 8, 0], &[0; 4], &[0; 4], &[0; 4]]));
+
+        let grid = torus(&[&[1, 0, 2], &[0, 0, 0], &[3, 0, 4]]);
+        assert!(grid.has_moves());
+        for direction in Direction::ALL {
+            assert!(grid.can_move(direction), "{:?}", direction);
+        }
+
+        // any move of the square board moves the torus too
+        let spawn_table = SpawnTable::default();
+        let mut rng = GameRng::new(0);
+        for _ in 0..500 {
+            let mut square = Grid::new(4, 4);
+            for _ in 0..rng.gen_range(1..16) {
+                square.spawn(&spawn_table, &mut rng);
+            }
+            let mut torus = Grid::torus(4, 4);
+            for (cell, tile) in square.tiles() {
+                torus.set(cell, Some(tile));
+            }
+            for direction in Direction::ALL {
+                assert!(!square.can_move(direction) || torus.can_move(direction));
+            }
+        }
+    }
+
+    #[test]
+    fn hex_board_sizes() {
+        assert_eq!(Grid::hex(1).cells().count(), 1);
+        assert_eq!(Grid::hex(128).width(), 255);
+        assert!(std::panic::catch_unwind(|| Grid::hex(0)).is_err());
+        assert!(std::panic::catch_unwind(|| Grid::hex(129)).is_err());
+    }
+
+    #[test]
+    fn torus_wraps_across_edges() {
+        let mut grid = torus(&[&[0, 1, 0, 1], &[1, 2, 0, 1], &[1, 0, 0, 0], &[1, 2, 3, 1]]);
+        let outcome = grid.shift(Direction::Left);
+        assert_eq!(
+            grid,
+            torus(&[&[2, 0, 0, 0], &[2, 0, 0, 2], &[1, 0, 0, 0], &[2, 3, 0, 2]])
+        );
+        assert_eq!(outcome.score, 4 + 4 + 4);
+
+        let mut grid = torus(&[&[1, 2, 3, 4], &[2, 1, 2, 1], &[1, 2, 1, 2], &[2, 1, 2, 1]]);
+        assert!(!grid.has_moves());
+        grid.set(Cell { x: 3, y: 3 }, Some(Tile::number(1)));
+        assert!(grid.can_move(Direction::Left));
+        assert!(grid.can_move(Direction::Right));
+
+        let mut grid = torus(&[&[1, 0, 0, 1]]);
+        grid.set_wall(Cell { x: 1, y: 0 }, true);
+        grid.shift(Direction::Right);
+        assert_eq!(grid.get(Cell { x: 0, y: 0 }), Some(Tile::number(2)));
+        assert_eq!(grid.tiles().count(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+use rand::prelude::*;
+
+use std::sync::OnceLock;
+
+use super::{Cell, Direction, Grid, Rules, Ruleset, Spawn, SpawnTable, Tile, TileKind, Topology};
+
+/// Highest level a 4 bit cell can hold. Tiles of this level don't merge.
+pub const MAX_LEVEL: u32 = 15;
+
+/// A 4x4 board packed in a `u64`, 4 bits per cell holding the tile level
+/// (0 for an empty cell), cell `(x, y)` being the nibble `y * 4 + x`.
+///
+/// Moves are looked up row by row in tables computed once, which makes this
+/// a fast alternative to [`Grid`] for solvers and simulations. Both give the
+/// same results as long as no tile goes past [`MAX_LEVEL`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BitBoard(pub u64);
+
+struct Tables {
+    // shifted row and points gained, by row value
+    left: Vec<u16>,
+    left_score: Vec<u32>,
+    right: Vec<u16>,
+    right_score: Vec<u32>,
+}
+
+impl Tables {
+    fn new() -> Self {
+        let (left, left_score) = (0..=u16::MAX).map(shift_row_left).unzip();
+        let (right, right_score) = (0..=u16::MAX)
+            .map(|row| {
+                let (shifted, score) = shift_row_left(reverse_row(row));
+                (reverse_row(shifted), score)
+            })
+            .unzip();
+        Self {
+            left,
+            left_score,
+            right,
+            right_score,
+        }
+    }
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(Tables::new)
+}
+
+fn reverse_row(row: u16) -> u16 {
+    (row >> 12) | ((row >> 4) & 0x00f0) | ((row << 4) & 0x0f00) | (row << 12)
+}
+
+// moves the tiles of a row towards its first nibble
+fn shift_row_left(row: u16) -> (u16, u32) {
+    let mut tiles = (0..4)
+        .map(|x| u32::from((row >> (4 * x)) & 0xf))
+        .filter(|level| *level != 0)
+        .peekable();
+    let mut shifted = 0;
+    let mut score = 0;
+    let mut x = 0;
+    while let Some(level) = tiles.next() {
+        let level = match tiles.peek() {
+            Some(next) if *next == level && level < MAX_LEVEL => {
+                tiles.next();
+                score += 2u32.pow(level + 1);
+                level + 1
+            }
+            _ => level,
+        };
+        shifted |= (level as u16) << (4 * x);
+        x += 1;
+    }
+    (shifted, score)
+}
+
+// swaps cell (x, y) with cell (y, x)
+fn transpose(board: u64) -> u64 {
+    let a1 = board & 0xf0f0_0f0f_f0f0_0f0f;
+    let a2 = board & 0x0000_f0f0_0000_f0f0;
+    let a3 = board & 0x0f0f_0000_0f0f_0000;
+    let a = a1 | (a2 << 12) | (a3 >> 12);
+    let b1 = a & 0xff00_ff00_00ff_00ff;
+    let b2 = a & 0x00ff_00ff_0000_0000;
+    let b3 = a & 0x0000_0000_ff00_ff00;
+    b1 | (b2 >> 24) | (b3 << 24)
+}
+
+impl BitBoard {
+    /// Packs a 4x4 grid, returns `None` for other sizes, for grids with
+    /// walls or jokers, for levels above [`MAX_LEVEL`] or for other rules
+    /// than [`Rules::Classic`] on a square board.
+    pub fn from_grid(grid: &Grid) -> Option<Self> {
+        if grid.width() != 4
+            || grid.height() != 4
+            || grid.walls().next().is_some()
+            || grid.ruleset().name() != Rules::Classic.name()
+            || grid.topology() != Topology::Square
+        {
+            return None;
+        }
+        let mut board = 0;
+        for (cell, tile) in grid.tiles() {
+            if tile.kind != TileKind::Number || tile.level > MAX_LEVEL {
+                return None;
+            }
+            board |= u64::from(tile.level) << Self::shift_of(cell);
+        }
+        Some(Self(board))
+    }
+
+    pub fn to_grid(self) -> Grid {
+        let mut grid = Grid::new(4, 4);
+        for cell in grid.cells().collect::<Vec<_>>() {
+            grid.set(cell, self.get(cell).map(Tile::number));
+        }
+        grid
+    }
+
+    fn shift_of(cell: Cell) -> u32 {
+        4 * (u32::from(cell.y) * 4 + u32::from(cell.x))
+    }
+
+    /// Returns the level of the tile at `cell`, if any.
+    pub fn get(self, cell: Cell) -> Option<u32> {
+        match (self.0 >> Self::shift_of(cell)) & 0xf {
+            0 => None,
+            level => Some(level as u32),
+        }
+    }
+
+    /// Puts a tile of `level` at `cell`, or empties it.
+    ///
+    /// Panics if `level` is above [`MAX_LEVEL`].
+    pub fn set(&mut self, cell: Cell, level: Option<u32>) {
+        let level = level.unwrap_or(0);
+        assert!(level <= MAX_LEVEL, "a bitboard can't hold level {}", level);
+        let level = u64::from(level);
+        let shift = Self::shift_of(cell);
+        self.0 = (self.0 & !(0xf << shift)) | (level << shift);
+    }
+
+    /// Empty cells, in the same order as [`Grid::empty_cells`].
+    pub fn empty_cells(self) -> impl Iterator<Item = Cell> {
+        (0..16u8)
+            .filter(move |index| (self.0 >> (4 * index)) & 0xf == 0)
+            .map(|index| Cell {
+                x: index % 4,
+                y: index / 4,
+            })
+    }
+
+    pub fn max_level(self) -> Option<u32> {
+        (0..16)
+            .map(|index| ((self.0 >> (4 * index)) & 0xf) as u32)
+            .filter(|level| *level != 0)
+            .max()
+    }
+
+    // applies a row table to every row
+    fn shift_rows(board: u64, rows: &[u16], score: &[u32]) -> (u64, u32) {
+        (0..4).fold((0, 0), |(shifted, points), y| {
+            let row = ((board >> (16 * y)) & 0xffff) as usize;
+            (
+                shifted | (u64::from(rows[row]) << (16 * y)),
+                points + score[row],
+            )
+        })
+    }
+
+    /// Slides every tile as far as possible in `direction`, like
+    /// [`Grid::shift`]. Returns the new board and the points gained.
+    pub fn shift(self, direction: Direction) -> (Self, u64) {
+        let tables = tables();
+        let (board, score) = match direction {
+            Direction::Left => Self::shift_rows(self.0, &tables.left, &tables.left_score),
+            Direction::Right => Self::shift_rows(self.0, &tables.right, &tables.right_score),
+            Direction::Down => {
+                let (board, score) =
+                    Self::shift_rows(transpose(self.0), &tables.left, &tables.left_score);
+                (transpose(board), score)
+            }
+            Direction::Up => {
+                let (board, score) =
+                    Self::shift_rows(transpose(self.0), &tables.right, &tables.right_score);
+                (transpose(board), score)
+            }
+            // hex directions, nothing moves
+            _ => (self.0, 0),
+        };
+        (Self(board), u64::from(score))
+    }
+
+    pub fn can_move(self, direction: Direction) -> bool {
+        self.shift(direction).0 != self
+    }
+
+    pub fn has_moves(self) -> bool {
+        Direction::ALL
+            .iter()
+            .any(|direction| self.can_move(*direction))
+    }
+
+    /// Like [`Grid::play`]: shifts, then spawns a tile if anything moved.
+    /// Returns the points gained, or `None` if no tile could move.
+    pub fn play<R: Rng + ?Sized>(
+        &mut self,
+        direction: Direction,
+        spawn_table: &SpawnTable,
+        rng: &mut R,
+    ) -> Option<u64> {
+        let (board, score) = self.shift(direction);
+        if board == *self {
+            return None;
+        }
+        *self = board;
+        self.spawn(spawn_table, rng);
+        Some(score)
+    }
+
+    /// Like [`Grid::spawn`], drawing the same numbers from `rng`.
+    ///
+    /// Panics if `spawn_table` picked a joker or a level above [`MAX_LEVEL`].
+    pub fn spawn<R: Rng + ?Sized>(
+        &mut self,
+        spawn_table: &SpawnTable,
+        rng: &mut R,
+    ) -> Option<Spawn> {
+        let cells = self.empty_cells().collect::<Vec<_>>();
+        let cell = *cells.choose(rng)?;
+        let spawn = Spawn {
+            cell,
+            tile: spawn_table.sample(rng),
+        };
+        assert_eq!(
+            spawn.tile.kind,
+            TileKind::Number,
+            "a bitboard can't hold jokers"
+        );
+        self.set(cell, Some(spawn.tile.level));
+        Some(spawn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::GameRng;
+
+    use std::sync::Arc;
+
+    // random seeded games, played move for move on both boards
+    #[test]
+    fn plays_like_grid() {
+        let spawn_table = SpawnTable::default();
+        for seed in 0..200 {
+            let mut grid = Grid::new(4, 4);
+            let mut grid_rng = GameRng::new(seed);
+            grid.spawn(&spawn_table, &mut grid_rng);
+            grid.spawn(&spawn_table, &mut grid_rng);
+            let mut board = BitBoard::from_grid(&grid).unwrap();
+            let mut board_rng = grid_rng;
+            let mut moves_rng = GameRng::new(!seed);
+            while grid.has_moves() {
+                assert!(board.has_moves());
+                let direction = *Direction::ALL.choose(&mut moves_rng).unwrap();
+                let outcome = grid.play(direction, &spawn_table, &mut grid_rng);
+                let score = board.play(direction, &spawn_table, &mut board_rng);
+                assert_eq!(score, outcome.map(|outcome| outcome.score));
+                assert_eq!(board.to_grid(), grid, "seed {} {:?}", seed, direction);
+            }
+            assert!(!board.has_moves());
+            assert_eq!(board.max_level(), grid.max_level());
+        }
+    }
+
+    #[test]
+    fn rejects_boards_it_cant_hold() {
+        assert!(BitBoard::from_grid(&Grid::new(5, 4)).is_none());
+        assert!(BitBoard::from_grid(&Grid::with_walls(4, 4, &[Cell { x: 0, y: 0 }])).is_none());
+        assert!(
+            BitBoard::from_grid(&Grid::new(4, 4).with_ruleset(Arc::new(Rules::Threes))).is_none()
+        );
+        let mut grid = Grid::new(4, 4);
+        grid.set(Cell { x: 1, y: 2 }, Some(Tile::JOKER));
+        assert!(BitBoard::from_grid(&grid).is_none());
+        grid.set(Cell { x: 1, y: 2 }, Some(Tile::number(MAX_LEVEL + 1)));
+        assert!(BitBoard::from_grid(&grid).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_panics_above_max_level() {
+        BitBoard::default().set(Cell { x: 0, y: 0 }, Some(MAX_LEVEL + 1));
+    }
+}
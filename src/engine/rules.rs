@@ -0,0 +1,247 @@
+use std::fmt;
+
+use super::{Grid, SpawnTable, Tile, TileKind};
+
+/// The rules of a game variant: which tiles merge and into what, what a
+/// merge is worth, which tiles spawn, when the game is won and what is
+/// written on tiles.
+///
+/// Levels start at 1 for the smallest tile, only the ruleset gives them a
+/// meaning. Jokers are handed over like any other tile, it's up to the
+/// ruleset whether they merge. [`Rules`] are the built-in rulesets, other
+/// crates can implement this trait and pass their own to
+/// [`Settings::ruleset`](crate::Settings::ruleset).
+pub trait Ruleset: fmt::Debug + Send + Sync {
+    /// Short unique name of at most 255 bytes. [`Rules::from_name`] picks
+    /// built-in rulesets by name, and share codes hold it so they are loaded
+    /// with the ruleset they were made with.
+    fn name(&self) -> &str;
+
+    /// Number of neighbouring tiles that merge together.
+    fn merge_size(&self) -> usize {
+        2
+    }
+
+    /// Whether `tiles`, [`Ruleset::merge_size`] neighbours ordered from the
+    /// edge the tiles move towards, merge.
+    fn can_merge(&self, tiles: &[Tile]) -> bool;
+
+    /// The tile `tiles` merge into, only called when they
+    /// [can merge](Ruleset::can_merge).
+    fn merge(&self, tiles: &[Tile]) -> Tile;
+
+    /// Points for merging into `tile`.
+    fn merge_score(&self, tile: Tile) -> u64;
+
+    /// The tiles spawned in a new game, and their odds.
+    fn spawn_table(&self) -> SpawnTable;
+
+    /// The level a tile has to reach to win, unless set in the settings.
+    fn win_level(&self) -> u32;
+
+    /// Whether the game is won, by default once a tile reaches `win_level`.
+    fn has_won(&self, grid: &Grid, win_level: u32) -> bool {
+        grid.max_level() >= Some(win_level)
+    }
+
+    /// The text on a numbered tile of `level`.
+    fn label(&self, level: u32) -> String;
+
+    /// Which of the classic tile colors a tile of `level` gets, its level by
+    /// default.
+    fn shade(&self, level: u32) -> u32 {
+        level
+    }
+
+    /// An RGB color replacing the classic ones for a tile of `level`.
+    fn color(&self, _level: u32) -> Option<[u8; 3]> {
+        None
+    }
+}
+
+/// The built-in rulesets.
+///
+/// Levels grow by one per merge, only the values they stand for change
+/// between rulesets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Rules {
+    /// Two equal tiles merge, values are powers of two (2048).
+    #[default]
+    Classic,
+    /// Two neighbouring Fibonacci numbers merge into the next one, and so do
+    /// two 1s (2584).
+    Fibonacci,
+    /// 1 and 2 merge into 3, then two equal multiples of 3 merge (Threes).
+    Threes,
+    /// Three equal tiles merge, values are powers of three (2187).
+    PowersOfThree,
+}
+
+impl Rules {
+    pub const ALL: [Rules; 4] = [
+        Rules::Classic,
+        Rules::Fibonacci,
+        Rules::Threes,
+        Rules::PowersOfThree,
+    ];
+
+    /// The built-in ruleset called `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rules| rules.name() == name)
+    }
+
+    // the tile `tiles` merge into, if they can merge, jokers standing in
+    // for any number as long as there is one
+    fn merged(self, tiles: &[Tile]) -> Option<Tile> {
+        let levels = tiles
+            .iter()
+            .filter(|tile| tile.kind == TileKind::Number)
+            .map(|tile| tile.level)
+            .collect::<Vec<_>>();
+        let first = *levels.first()?;
+        if levels.len() < tiles.len() {
+            return levels
+                .iter()
+                .all(|level| *level == first)
+                .then(|| Tile::number(self.next_level(first)));
+        }
+        let merged = match (self, levels.as_slice()) {
+            (Rules::Fibonacci, [1, 1]) => Some(2),
+            (Rules::Fibonacci, [a, b]) if a.abs_diff(*b) == 1 => Some(a.max(b) + 1),
+            (Rules::Threes, [1, 2] | [2, 1]) => Some(3),
+            (Rules::Threes, [a, b]) if a == b && *a >= 3 => Some(a + 1),
+            (Rules::Classic | Rules::PowersOfThree, _) if levels.iter().all(|l| *l == first) => {
+                Some(first + 1)
+            }
+            _ => None,
+        };
+        merged.map(Tile::number)
+    }
+
+    // the level a tile of `level` grows to when it merges
+    fn next_level(self, level: u32) -> u32 {
+        match self {
+            Rules::Threes if level < 3 => 3,
+            _ => level + 1,
+        }
+    }
+
+    /// The number on a tile of `level`, `None` past `u64::MAX`.
+    pub fn value(self, level: u32) -> Option<u64> {
+        match self {
+            Rules::Classic => 2u64.checked_pow(level),
+            Rules::Fibonacci => {
+                let (mut a, mut b) = (1u64, 1u64);
+                for _ in 0..level {
+                    (a, b) = (b, a.checked_add(b)?);
+                }
+                Some(a)
+            }
+            Rules::Threes => match level {
+                0..=2 => Some(u64::from(level)),
+                _ => 2u64.checked_pow(level - 3)?.checked_mul(3),
+            },
+            Rules::PowersOfThree => 3u64.checked_pow(level),
+        }
+    }
+}
+
+impl Ruleset for Rules {
+    fn name(&self) -> &str {
+        match self {
+            Rules::Classic => "classic",
+            Rules::Fibonacci => "fibonacci",
+            Rules::Threes => "threes",
+            Rules::PowersOfThree => "powers-of-three",
+        }
+    }
+
+    fn merge_size(&self) -> usize {
+        match self {
+            Rules::PowersOfThree => 3,
+            _ => 2,
+        }
+    }
+
+    fn can_merge(&self, tiles: &[Tile]) -> bool {
+        self.merged(tiles).is_some()
+    }
+
+    fn merge(&self, tiles: &[Tile]) -> Tile {
+        self.merged(tiles).expect("tiles that can merge")
+    }
+
+    /// The value of the merged tile, saturating past `u64::MAX`. Threes
+    /// counts pow(3, n) for the nth multiple of 3 instead.
+    fn merge_score(&self, tile: Tile) -> u64 {
+        match self {
+            Rules::Threes if tile.level < 3 => 0,
+            Rules::Threes => 3u64.saturating_pow(tile.level - 2),
+            _ => self.value(tile.level).unwrap_or(u64::MAX),
+        }
+    }
+
+    /// The number on the tile, shortened with a metric suffix past 5 digits
+    /// (131072 is shown as 131K).
+    fn label(&self, level: u32) -> String {
+        let mut value = match self.value(level) {
+            Some(value) => value,
+            // past what the score can hold
+            None => {
+                return match self {
+                    Rules::Classic => format!("2^{}", level),
+                    Rules::Fibonacci => format!("F{}", level + 1),
+                    Rules::Threes => format!("3*2^{}", level - 3),
+                    Rules::PowersOfThree => format!("3^{}", level),
+                }
+            }
+        };
+        if value < 100_000 {
+            return value.to_string();
+        }
+        let mut suffixes = ["K", "M", "G", "T", "P", "E"].iter();
+        let mut suffix = "";
+        while value >= 1000 {
+            value /= 1000;
+            suffix = suffixes
+                .next()
+                .expect("a u64 has at most 6 groups of 3 digits");
+        }
+        format!("{}{}", value, suffix)
+    }
+
+    /// The tiles spawned in the original game.
+    fn spawn_table(&self) -> SpawnTable {
+        match self {
+            Rules::Threes => SpawnTable::new(vec![(1, 1), (2, 1), (3, 1)]),
+            _ => SpawnTable::default(),
+        }
+    }
+
+    /// The tile that wins the original game.
+    fn win_level(&self) -> u32 {
+        match self {
+            Rules::Classic => 11,
+            Rules::Fibonacci => 17,
+            Rules::Threes => 11,
+            Rules::PowersOfThree => 7,
+        }
+    }
+
+    /// Threes starts the color ramp at 3.
+    fn shade(&self, level: u32) -> u32 {
+        match self {
+            Rules::Threes => level.saturating_sub(2),
+            _ => level,
+        }
+    }
+
+    /// The blue 1 and red 2 of Threes.
+    fn color(&self, level: u32) -> Option<[u8; 3]> {
+        match (self, level) {
+            (Rules::Threes, 1) => Some([102, 204, 255]),
+            (Rules::Threes, 2) => Some([255, 102, 128]),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::{Cell, GameRng, Grid, Rules, Ruleset, Tile, TileKind, Topology};
+
+// base64url, without padding
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// cell bytes besides levels
+const EMPTY: u8 = 0;
+const JOKER: u8 = 0xfe;
+const WALL: u8 = 0xff;
+
+/// A game position that fits in a short string, to hand it to someone else
+/// or attach it to a bug report.
+///
+/// The code holds the name of the ruleset, the board with its tiles and
+/// walls, the score, the random number generator and the move count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedGame {
+    pub grid: Grid,
+    pub rng: GameRng,
+    pub score: u64,
+    pub moves: u32,
+}
+
+/// Why a share code couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareCodeError {
+    /// A character that can't be part of a code.
+    InvalidCharacter(char),
+    /// The code stops before the end of the game.
+    Truncated,
+    /// The code goes on after the end of the game.
+    TrailingData,
+    /// The code was made by a newer version of the game.
+    UnsupportedVersion(u8),
+    /// The checksum doesn't match, the code was probably mistyped.
+    Checksum,
+    /// The board has an unknown shape or an invalid size.
+    InvalidBoard,
+    /// A cell holds a level that can't be on the board, or is outside of
+    /// a hex board.
+    InvalidCell(Cell),
+    /// A number is too big for what it counts, or is encoded on too many
+    /// bytes.
+    InvalidNumber,
+    /// The code plays by a ruleset that isn't built in, with its name.
+    UnknownRuleset(String),
+    /// The code plays by another ruleset than the game it is loaded into,
+    /// with its name.
+    WrongRuleset(String),
+}
+
+impl fmt::Display for ShareCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShareCodeError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            ShareCodeError::Truncated => write!(f, "the code is too short"),
+            ShareCodeError::TrailingData => write!(f, "the code is too long"),
+            ShareCodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported code version {}", version)
+            }
+            ShareCodeError::Checksum => write!(f, "wrong checksum, check for typos"),
+            ShareCodeError::InvalidBoard => write!(f, "invalid board"),
+            ShareCodeError::InvalidCell(cell) => {
+                write!(f, "invalid cell ({}, {})", cell.x, cell.y)
+            }
+            ShareCodeError::InvalidNumber => write!(f, "invalid number"),
+            ShareCodeError::UnknownRuleset(name) => write!(f, "unknown rules {:?}", name),
+            ShareCodeError::WrongRuleset(name) => {
+                write!(f, "the code plays by the {} rules", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShareCodeError {}
+
+impl SharedGame {
+    /// Version written at the start of every code, bumped on layout changes.
+    pub const VERSION: u8 = 2;
+
+    /// The characters codes are made of.
+    pub const ALPHABET: &'static [u8; 64] = ALPHABET;
+
+    /// Packs the game in a base64url string. The bytes are the version, the
+    /// length and name of the ruleset, topology, width and height, a byte per
+    /// cell of the bounding rectangle row by row, the score, seed, draws and
+    /// moves, and a checksum.
+    ///
+    /// Panics if the name of the ruleset is longer than 255 bytes.
+    pub fn encode(&self) -> String {
+        let grid = &self.grid;
+        let name = grid.ruleset().name();
+        let mut bytes = vec![
+            Self::VERSION,
+            u8::try_from(name.len()).expect("ruleset names fit in 255 bytes"),
+        ];
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&[
+            match grid.topology() {
+                Topology::Square => 0,
+                Topology::Hex => 1,
+                Topology::Torus => 2,
+            },
+            grid.width(),
+            grid.height(),
+        ]);
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let cell = Cell { x, y };
+                bytes.push(if !grid.contains(cell) {
+                    EMPTY
+                } else if grid.is_wall(cell) {
+                    WALL
+                } else {
+                    match grid.get(cell) {
+                        None => EMPTY,
+                        Some(tile) if tile.kind == TileKind::Joker => JOKER,
+                        Some(tile) => u8::try_from(tile.level)
+                            .ok()
+                            .filter(|level| *level < JOKER)
+                            .expect("levels of a share code fit in a byte"),
+                    }
+                });
+            }
+        }
+        write_varint(&mut bytes, self.score);
+        bytes.extend_from_slice(&self.rng.seed().to_le_bytes());
+        write_varint(&mut bytes, self.rng.draws());
+        write_varint(&mut bytes, u64::from(self.moves));
+        let checksum = fletcher16(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        encode_base64(&bytes)
+    }
+
+    /// Reads a code made by [`SharedGame::encode`], ignoring whitespace.
+    /// The grid plays by the built-in ruleset named in the code, see
+    /// [`Rules::from_name`].
+    pub fn decode(code: &str) -> Result<Self, ShareCodeError> {
+        Self::read(code, |name| match Rules::from_name(name) {
+            Some(rules) => Ok(Arc::new(rules)),
+            None => Err(ShareCodeError::UnknownRuleset(name.to_string())),
+        })
+    }
+
+    /// Reads a code of a game playing by `ruleset`, which doesn't have to be
+    /// built in, failing with [`ShareCodeError::WrongRuleset`] on codes
+    /// of other rulesets.
+    pub fn decode_with_ruleset(
+        code: &str,
+        ruleset: Arc<dyn Ruleset>,
+    ) -> Result<Self, ShareCodeError> {
+        Self::read(code, |name| {
+            if name == ruleset.name() {
+                Ok(ruleset)
+            } else {
+                Err(ShareCodeError::WrongRuleset(name.to_string()))
+            }
+        })
+    }
+
+    // `ruleset` looks up the ruleset of the name in the code
+    fn read(
+        code: &str,
+        ruleset: impl FnOnce(&str) -> Result<Arc<dyn Ruleset>, ShareCodeError>,
+    ) -> Result<Self, ShareCodeError> {
+        let bytes = decode_base64(code)?;
+        let version = *bytes.first().ok_or(ShareCodeError::Truncated)?;
+        if version != Self::VERSION {
+            return Err(ShareCodeError::UnsupportedVersion(version));
+        }
+        if bytes.len() < 3 {
+            return Err(ShareCodeError::Truncated);
+        }
+        let (payload, checksum) = bytes.split_at(bytes.len() - 2);
+        if fletcher16(payload).to_le_bytes() != checksum {
+            return Err(ShareCodeError::Checksum);
+        }
+
+        let mut reader = Reader(&payload[1..]);
+        let name_len = usize::from(reader.byte()?);
+        let ruleset = ruleset(&String::from_utf8_lossy(reader.take(name_len)?))?;
+        let (topology, width, height) = (reader.byte()?, reader.byte()?, reader.byte()?);
+        if width == 0 || height == 0 {
+            return Err(ShareCodeError::InvalidBoard);
+        }
+        let mut grid = match topology {
+            0 => Grid::new(width, height),
+            1 if width == height && width % 2 == 1 => Grid::hex(width / 2 + 1),
+            2 => Grid::torus(width, height),
+            _ => return Err(ShareCodeError::InvalidBoard),
+        }
+        .with_ruleset(ruleset);
+        for y in 0..height {
+            for x in 0..width {
+                let cell = Cell { x, y };
+                match reader.byte()? {
+                    EMPTY => {}
+                    _ if !grid.contains(cell) => return Err(ShareCodeError::InvalidCell(cell)),
+                    WALL => grid.set_wall(cell, true),
+                    JOKER => grid.set(cell, Some(Tile::JOKER)),
+                    level => grid.set(cell, Some(Tile::number(u32::from(level)))),
+                }
+            }
+        }
+        let score = reader.varint()?;
+        let seed = u64::from_le_bytes(reader.bytes::<8>()?);
+        let draws = reader.varint()?;
+        let moves = u32::try_from(reader.varint()?).map_err(|_| ShareCodeError::InvalidNumber)?;
+        if !reader.0.is_empty() {
+            return Err(ShareCodeError::TrailingData);
+        }
+
+        Ok(Self {
+            grid,
+            rng: GameRng::with_draws(seed, draws),
+            score,
+            moves,
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, ShareCodeError> {
+        let (first, rest) = self.0.split_first().ok_or(ShareCodeError::Truncated)?;
+        self.0 = rest;
+        Ok(*first)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], ShareCodeError> {
+        if self.0.len() < len {
+            return Err(ShareCodeError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], ShareCodeError> {
+        let mut bytes = [0; N];
+        for byte in bytes.iter_mut() {
+            *byte = self.byte()?;
+        }
+        Ok(bytes)
+    }
+
+    // LEB128, 7 bits per byte starting with the lowest
+    fn varint(&mut self) -> Result<u64, ShareCodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            // the tenth byte only has room for the highest bit
+            if bits << shift >> shift != bits {
+                return Err(ShareCodeError::InvalidNumber);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ShareCodeError::InvalidNumber)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn fletcher16(bytes: &[u8]) -> u16 {
+    let (sum1, sum2) = bytes.iter().fold((0u16, 0u16), |(sum1, sum2), byte| {
+        let sum1 = (sum1 + u16::from(*byte)) % 255;
+        (sum1, (sum2 + sum1) % 255)
+    });
+    (sum2 << 8) | sum1
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut code = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..=chunk.len() {
+            code.push(char::from(
+                ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize],
+            ));
+        }
+    }
+    code
+}
+
+fn decode_base64(code: &str) -> Result<Vec<u8>, ShareCodeError> {
+    let digits = code
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| {
+            u8::try_from(c)
+                .ok()
+                .and_then(|byte| ALPHABET.iter().position(|digit| *digit == byte))
+                .map(|digit| digit as u32)
+                .ok_or(ShareCodeError::InvalidCharacter(c))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return Err(ShareCodeError::Truncated);
+        }
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, digit)| group | digit << (18 - 6 * i));
+        for i in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the length and name of the ruleset in codes of classic games
+    const CLASSIC: &[u8] = b"\x07classic";
+    // where the board starts in codes of classic games
+    const BOARD: usize = 1 + CLASSIC.len();
+
+    // a code for `bytes`, with a valid checksum
+    fn code(bytes: &[u8]) -> String {
+        let mut bytes = bytes.to_vec();
+        bytes.extend_from_slice(&fletcher16(&bytes).to_le_bytes());
+        encode_base64(&bytes)
+    }
+
+    // a classic 1x1 square board with a tile of level 3, then score, seed,
+    // draws and moves
+    fn one_cell(moves: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![SharedGame::VERSION];
+        bytes.extend_from_slice(CLASSIC);
+        bytes.extend_from_slice(&[0, 1, 1, 3, 7]);
+        bytes.extend_from_slice(&5u64.to_le_bytes());
+        bytes.push(2);
+        bytes.extend_from_slice(moves);
+        bytes
+    }
+
+    fn shared(grid: Grid) -> SharedGame {
+        SharedGame {
+            grid,
+            rng: GameRng::with_draws(0xdead_beef_1234, 300),
+            score: 123_456,
+            moves: 77,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid::new(4, 5);
+        grid.set(Cell { x: 0, y: 4 }, Some(Tile::number(1)));
+        grid.set(Cell { x: 1, y: 3 }, Some(Tile::JOKER));
+        grid.set(Cell { x: 3, y: 0 }, Some(Tile::number(17)));
+        grid.set_wall(Cell { x: 2, y: 2 }, true);
+        let mut torus = Grid::torus(3, 3);
+        torus.set(Cell { x: 2, y: 1 }, Some(Tile::number(2)));
+        let mut hex = Grid::hex(3);
+        hex.set(Cell { x: 4, y: 0 }, Some(Tile::number(5)));
+        for grid in [grid, torus, hex] {
+            let shared = shared(grid);
+            assert_eq!(SharedGame::decode(&shared.encode()), Ok(shared));
+        }
+
+        let big = SharedGame {
+            rng: GameRng::with_draws(u64::MAX, u64::MAX),
+            score: u64::MAX,
+            moves: u32::MAX,
+            ..shared(Grid::new(3, 3))
+        };
+        assert_eq!(SharedGame::decode(&big.encode()), Ok(big));
+    }
+
+    #[test]
+    fn codes_hold_the_ruleset() {
+        for rules in Rules::ALL {
+            let mut grid = Grid::new(3, 3).with_ruleset(Arc::new(rules));
+            grid.set(Cell { x: 1, y: 1 }, Some(Tile::number(4)));
+            let shared = shared(grid);
+            let code = shared.encode();
+            assert_eq!(SharedGame::decode(&code), Ok(shared.clone()));
+            assert_eq!(
+                SharedGame::decode_with_ruleset(&code, Arc::new(rules)),
+                Ok(shared)
+            );
+        }
+
+        let fibonacci = shared(Grid::new(3, 3).with_ruleset(Arc::new(Rules::Fibonacci)));
+        assert_eq!(
+            SharedGame::decode_with_ruleset(&fibonacci.encode(), Arc::new(Rules::Classic)),
+            Err(ShareCodeError::WrongRuleset("fibonacci".to_string()))
+        );
+        let mut unknown = vec![SharedGame::VERSION, 4];
+        unknown.extend_from_slice(b"nope");
+        unknown.extend_from_slice(&one_cell(&[1])[BOARD..]);
+        assert_eq!(
+            SharedGame::decode(&code(&unknown)),
+            Err(ShareCodeError::UnknownRuleset("nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn decode_ignores_whitespace() {
+        let shared = shared(Grid::new(3, 3));
+        let code = shared.encode();
+        let (start, end) = code.split_at(10);
+        let spaced = format!(" {}\n{} ", start, end);
+        assert_eq!(SharedGame::decode(&spaced), Ok(shared));
+    }
+
+    #[test]
+    fn malformed_codes() {
+        let valid = shared(Grid::new(4, 4)).encode();
+        let decode = |code: &str| SharedGame::decode(code).unwrap_err();
+
+        assert_eq!(decode("AQ!"), ShareCodeError::InvalidCharacter('!'));
+        assert_eq!(decode(""), ShareCodeError::Truncated);
+        assert_eq!(
+            decode(&code(&one_cell(&[])[..3])),
+            ShareCodeError::Truncated
+        );
+        assert_eq!(
+            decode(&code(&one_cell(&[])[..BOARD + 9])),
+            ShareCodeError::Truncated
+        );
+        let mut trailing = one_cell(&[1]);
+        trailing.push(0);
+        assert_eq!(decode(&code(&trailing)), ShareCodeError::TrailingData);
+        assert_eq!(
+            decode(&code(&[SharedGame::VERSION + 1])),
+            ShareCodeError::UnsupportedVersion(SharedGame::VERSION + 1)
+        );
+
+        let mut typo = valid.into_bytes();
+        typo[10] = if typo[10] == b'A' { b'B' } else { b'A' };
+        assert_eq!(
+            decode(std::str::from_utf8(&typo).unwrap()),
+            ShareCodeError::Checksum
+        );
+
+        let mut shape = one_cell(&[1]);
+        shape[BOARD] = 3;
+        assert_eq!(decode(&code(&shape)), ShareCodeError::InvalidBoard);
+        shape[BOARD..BOARD + 3].copy_from_slice(&[1, 2, 2]);
+        assert_eq!(decode(&code(&shape)), ShareCodeError::InvalidBoard);
+        shape[BOARD..BOARD + 3].copy_from_slice(&[0, 0, 1]);
+        assert_eq!(decode(&code(&shape)), ShareCodeError::InvalidBoard);
+
+        // a 3x3 hex board with a tile in the corner it doesn't have
+        let mut corner = one_cell(&[1]);
+        corner.splice(BOARD..BOARD + 4, [1, 3, 3, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            decode(&code(&corner)),
+            ShareCodeError::InvalidCell(Cell { x: 0, y: 0 })
+        );
+
+        let too_many_moves = one_cell(&[0x80, 0x80, 0x80, 0x80, 0x10]);
+        assert_eq!(
+            decode(&code(&too_many_moves)),
+            ShareCodeError::InvalidNumber
+        );
+        let too_long = one_cell(&[0x80; 11]);
+        assert_eq!(decode(&code(&too_long)), ShareCodeError::InvalidNumber);
+        let mut overflowing = vec![0xff; 9];
+        overflowing.push(0x02);
+        assert_eq!(
+            decode(&code(&one_cell(&overflowing))),
+            ShareCodeError::InvalidNumber
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::time::{Duration, Instant};
+
+use super::bitboard::MAX_LEVEL;
+use super::{BitBoard, Direction, Grid, Rules, Ruleset, SpawnTable, Tile, TileKind};
+
+// spawn sequences less likely than this are cut short, they barely change
+// the expected values and make up most of the search tree
+const MIN_PROBABILITY: f64 = 1e-4;
+
+// boards with no move left are worth as much as this many empty cells, less
+// than none
+const GAME_OVER_CELLS: f64 = -16.0;
+
+/// Searches the best move with expectimax: the player picks the move worth
+/// the most, spawns are averaged over every empty cell and tile of the spawn
+/// table by their odds.
+///
+/// Boards are worth the points scored on the way plus a bonus for empty
+/// cells at the end of the search, which keeps the board open for merges to
+/// come. Classic 4x4 games are searched on a [`BitBoard`], anything else on
+/// a [`Grid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solver {
+    /// Number of moves looked ahead, including the one to play.
+    pub depth: u32,
+    /// Number of tiles spawned after every move, one after the other like
+    /// [`Grid::play_with_spawns`].
+    pub spawns_per_move: u32,
+    /// Time after which the search stops deepening, unlimited when `None`.
+    /// A search always looks one move ahead whatever the budget.
+    pub time_budget: Option<Duration>,
+}
+
+/// How good a move is, see [`Solver::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveEvaluation {
+    pub direction: Direction,
+    /// Points expected to be scored over the searched moves.
+    pub expected_score: f64,
+    /// Expected score plus the worth of the boards the search ended on,
+    /// what moves are ranked by.
+    pub value: f64,
+    /// Number of moves that were looked ahead within the time budget.
+    pub depth: u32,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Solver {
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth: depth.max(1),
+            spawns_per_move: 1,
+            time_budget: None,
+        }
+    }
+
+    /// Expects `spawns` tiles to spawn after every move.
+    pub fn with_spawns(mut self, spawns: u32) -> Self {
+        self.spawns_per_move = spawns;
+        self
+    }
+
+    /// Stops deepening the search after `budget`.
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// The moves that can be played on `grid`, best first. Empty when the
+    /// game is over.
+    pub fn evaluate(&self, grid: &Grid, spawn_table: &SpawnTable) -> Vec<MoveEvaluation> {
+        let fits_bitboard = spawn_table
+            .weights()
+            .iter()
+            .all(|(tile, _)| tile.kind == TileKind::Number && tile.level <= MAX_LEVEL);
+        match BitBoard::from_grid(grid).filter(|_| fits_bitboard) {
+            Some(board) => self.search(&board, spawn_table),
+            None => self.search(grid, spawn_table),
+        }
+    }
+
+    /// The best move on `grid`, `None` when the game is over.
+    pub fn best_move(&self, grid: &Grid, spawn_table: &SpawnTable) -> Option<Direction> {
+        self.evaluate(grid, spawn_table)
+            .first()
+            .map(|evaluation| evaluation.direction)
+    }
+
+    // deepens the search one move at a time until the depth or the time
+    // budget is reached, keeping the last complete ranking
+    fn search<B: Board>(&self, board: &B, spawn_table: &SpawnTable) -> Vec<MoveEvaluation> {
+        let deadline = self.time_budget.map(|budget| Instant::now() + budget);
+        let mut ranking = Vec::new();
+        for depth in 1..=self.depth {
+            let search = Search {
+                spawn_table,
+                spawns: self.spawns_per_move,
+                // the first move is always searched to the end
+                deadline: deadline.filter(|_| depth > 1),
+            };
+            let evaluations = board
+                .directions()
+                .iter()
+                .filter_map(|direction| {
+                    let (next, points) = board.play(*direction)?;
+                    Some(search.chance(&next, depth - 1, 1.0).map(|outcome| {
+                        let outcome = outcome.add(points);
+                        MoveEvaluation {
+                            direction: *direction,
+                            expected_score: outcome.score,
+                            value: outcome.value,
+                            depth,
+                        }
+                    }))
+                })
+                .collect::<Option<Vec<_>>>();
+            match evaluations {
+                Some(evaluations) => ranking = evaluations,
+                None => break,
+            }
+        }
+        ranking.sort_by(|a, b| b.value.total_cmp(&a.value));
+        ranking
+    }
+}
+
+// the expected points and worth of a position
+#[derive(Clone, Copy)]
+struct Outcome {
+    score: f64,
+    value: f64,
+}
+
+impl Outcome {
+    fn add(self, points: u64) -> Self {
+        Self {
+            score: self.score + points as f64,
+            value: self.value + points as f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Search<'a> {
+    spawn_table: &'a SpawnTable,
+    spawns: u32,
+    deadline: Option<Instant>,
+}
+
+impl Search<'_> {
+    // the player's turn, `None` once out of time
+    fn player<B: Board>(&self, board: &B, depth: u32, probability: f64) -> Option<Outcome> {
+        let mut best: Option<Outcome> = None;
+        for direction in board.directions() {
+            if let Some((next, points)) = board.play(*direction) {
+                let outcome = self.chance(&next, depth - 1, probability)?.add(points);
+                if !matches!(best, Some(best) if best.value >= outcome.value) {
+                    best = Some(outcome);
+                }
+            }
+        }
+        Some(best.unwrap_or_else(|| board.leaf()))
+    }
+
+    // the spawns after a move, `None` once out of time
+    fn chance<B: Board>(&self, board: &B, depth: u32, probability: f64) -> Option<Outcome> {
+        if depth == 0 {
+            return Some(board.leaf());
+        }
+        if matches!(self.deadline, Some(deadline) if Instant::now() > deadline) {
+            return None;
+        }
+        self.spawn(board, self.spawns, depth, probability)
+    }
+
+    // spawns `left` more tiles before the player's turn, fewer once the
+    // board is full
+    fn spawn<B: Board>(
+        &self,
+        board: &B,
+        left: u32,
+        depth: u32,
+        probability: f64,
+    ) -> Option<Outcome> {
+        if probability < MIN_PROBABILITY {
+            return Some(board.leaf());
+        }
+        let spawns = match left {
+            0 => Vec::new(),
+            _ => board.spawns(self.spawn_table),
+        };
+        if spawns.is_empty() {
+            return self.player(board, depth, probability);
+        }
+        let mut expected = Outcome {
+            score: 0.0,
+            value: 0.0,
+        };
+        for (next, odds) in spawns {
+            let outcome = self.spawn(&next, left - 1, depth, probability * odds)?;
+            expected.score += odds * outcome.score;
+            expected.value += odds * outcome.value;
+        }
+        Some(expected)
+    }
+}
+
+// what the search needs from a board, so it runs on grids and bitboards
+trait Board: Sized {
+    fn directions(&self) -> &'static [Direction];
+
+    // the board after a move and the points scored, `None` if nothing moved
+    fn play(&self, direction: Direction) -> Option<(Self, u64)>;
+
+    // every board a spawn can lead to, with its odds
+    fn spawns(&self, spawn_table: &SpawnTable) -> Vec<(Self, f64)>;
+
+    fn empty_cells(&self) -> usize;
+
+    fn has_moves(&self) -> bool;
+
+    // points for merging into the highest tile, what an empty cell is worth
+    fn cell_value(&self) -> f64;
+
+    // the worth of a board the search ends on
+    fn leaf(&self) -> Outcome {
+        let empty_cells = self.empty_cells();
+        let cells = if empty_cells == 0 && !self.has_moves() {
+            GAME_OVER_CELLS
+        } else {
+            empty_cells as f64
+        };
+        Outcome {
+            score: 0.0,
+            value: cells * self.cell_value(),
+        }
+    }
+}
+
+// total weight of a spawn table, to turn weights into odds
+fn total_weight(spawn_table: &SpawnTable) -> f64 {
+    spawn_table
+        .weights()
+        .iter()
+        .map(|(_, weight)| f64::from(*weight))
+        .sum()
+}
+
+impl Board for Grid {
+    fn directions(&self) -> &'static [Direction] {
+        self.topology().directions()
+    }
+
+    fn play(&self, direction: Direction) -> Option<(Self, u64)> {
+        let mut next = self.clone();
+        let outcome = next.shift(direction);
+        outcome.moved().then_some((next, outcome.score))
+    }
+
+    fn spawns(&self, spawn_table: &SpawnTable) -> Vec<(Self, f64)> {
+        let cells = self.empty_cells().collect::<Vec<_>>();
+        let total = total_weight(spawn_table) * cells.len() as f64;
+        cells
+            .iter()
+            .flat_map(|cell| {
+                spawn_table.weights().iter().map(move |(tile, weight)| {
+                    let mut next = self.clone();
+                    next.set(*cell, Some(*tile));
+                    (next, f64::from(*weight) / total)
+                })
+            })
+            .collect()
+    }
+
+    fn empty_cells(&self) -> usize {
+        Grid::empty_cells(self).count()
+    }
+
+    fn has_moves(&self) -> bool {
+        Grid::has_moves(self)
+    }
+
+    fn cell_value(&self) -> f64 {
+        let level = self.max_level().unwrap_or(1);
+        self.ruleset().merge_score(Tile::number(level)) as f64
+    }
+}
+
+impl Board for BitBoard {
+    fn directions(&self) -> &'static [Direction] {
+        &Direction::ALL
+    }
+
+    fn play(&self, direction: Direction) -> Option<(Self, u64)> {
+        let (next, points) = self.shift(direction);
+        (next != *self).then_some((next, points))
+    }
+
+    fn spawns(&self, spawn_table: &SpawnTable) -> Vec<(Self, f64)> {
+        let cells = BitBoard::empty_cells(*self).collect::<Vec<_>>();
+        let total = total_weight(spawn_table) * cells.len() as f64;
+        cells
+            .iter()
+            .flat_map(|cell| {
+                spawn_table.weights().iter().map(move |(tile, weight)| {
+                    let mut next = *self;
+                    next.set(*cell, Some(tile.level));
+                    (next, f64::from(*weight) / total)
+                })
+            })
+            .collect()
+    }
+
+    fn empty_cells(&self) -> usize {
+        BitBoard::empty_cells(*self).count()
+    }
+
+    fn has_moves(&self) -> bool {
+        BitBoard::has_moves(*self)
+    }
+
+    fn cell_value(&self) -> f64 {
+        let level = self.max_level().unwrap_or(1);
+        Rules::Classic.merge_score(Tile::number(level)) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Cell, GameRng};
+
+    use std::sync::Arc;
+
+    #[test]
+    fn ranks_merges_first() {
+        let mut grid = Grid::new(4, 4);
+        grid.set(Cell { x: 0, y: 3 }, Some(Tile::number(1)));
+        grid.set(Cell { x: 1, y: 3 }, Some(Tile::number(1)));
+        let ranking = Solver::new(2).evaluate(&grid, &SpawnTable::default());
+        assert_eq!(ranking.len(), 3);
+        assert!(matches!(
+            ranking[0].direction,
+            Direction::Left | Direction::Right
+        ));
+        assert!(ranking[0].expected_score >= 4.0);
+    }
+
+    // grids and bitboards, one spawn per move or more
+    #[test]
+    fn plays_games() {
+        let spawn_table = SpawnTable::default();
+        let boards = [
+            (Grid::new(4, 4), 1),
+            (Grid::new(4, 4), 3),
+            (Grid::hex(3), 2),
+            (Grid::new(4, 4).with_ruleset(Arc::new(Rules::Fibonacci)), 1),
+        ];
+        for (mut grid, spawns) in boards {
+            let solver = Solver::new(2).with_spawns(spawns);
+            let mut rng = GameRng::new(7);
+            grid.spawn(&spawn_table, &mut rng);
+            for _ in 0..50 {
+                match solver.best_move(&grid, &spawn_table) {
+                    Some(direction) => {
+                        grid.play_with_spawns(direction, &spawn_table, spawns, &mut rng)
+                            .expect("the best move moves tiles");
+                    }
+                    None => {
+                        assert!(!grid.has_moves());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use rand::prelude::*;
+
+use std::fmt;
+
+use super::{Direction, Grid, Solver, SpawnTable, Topology};
+
+/// Picks moves on its own, for autoplay, the simulator and bots of other
+/// crates.
+///
+/// Strategies are shared between threads, state that changes from one move
+/// to the next goes in the caller's generator or in the grid.
+pub trait Strategy: fmt::Debug + Send + Sync {
+    /// Short unique name, used to pick a strategy.
+    fn name(&self) -> &str;
+
+    /// The move to play on `grid`, `None` when no move is left. The move
+    /// has to move tiles. `spawn_table` is what tiles will spawn after the
+    /// move and `rng` is for strategies that pick moves at random.
+    fn next_move(
+        &self,
+        grid: &Grid,
+        spawn_table: &SpawnTable,
+        rng: &mut dyn RngCore,
+    ) -> Option<Direction>;
+}
+
+/// The built-in strategy called `name`, among those without settings.
+/// Expectimax is built with [`Solver::new`], for its depth and the spawns it
+/// expects per move.
+pub fn strategy_from_name(name: &str) -> Option<Box<dyn Strategy>> {
+    match name {
+        "random" => Some(Box::new(RandomMoves)),
+        "corner" => Some(Box::new(Corner)),
+        "greedy" => Some(Box::new(Greedy)),
+        _ => None,
+    }
+}
+
+/// Any move that moves tiles, the baseline every other strategy should beat.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomMoves;
+
+impl Strategy for RandomMoves {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn next_move(
+        &self,
+        grid: &Grid,
+        _spawn_table: &SpawnTable,
+        rng: &mut dyn RngCore,
+    ) -> Option<Direction> {
+        grid.topology()
+            .directions()
+            .iter()
+            .filter(|direction| grid.can_move(**direction))
+            .choose(rng)
+            .copied()
+    }
+}
+
+// moves towards the bottom left corner first, up last
+fn corner_order(grid: &Grid) -> &'static [Direction] {
+    if grid.topology() == Topology::Hex {
+        &[
+            Direction::Down,
+            Direction::DownLeft,
+            Direction::UpLeft,
+            Direction::DownRight,
+            Direction::UpRight,
+            Direction::Up,
+        ]
+    } else {
+        &[
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ]
+    }
+}
+
+/// Keeps the tiles in the bottom left corner: down if it can, then left,
+/// right and up as a last resort.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Corner;
+
+impl Strategy for Corner {
+    fn name(&self) -> &str {
+        "corner"
+    }
+
+    fn next_move(
+        &self,
+        grid: &Grid,
+        _spawn_table: &SpawnTable,
+        _rng: &mut dyn RngCore,
+    ) -> Option<Direction> {
+        corner_order(grid)
+            .iter()
+            .find(|direction| grid.can_move(**direction))
+            .copied()
+    }
+}
+
+/// The move scoring the most points right away, ties going to the
+/// [`Corner`] order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Greedy;
+
+impl Strategy for Greedy {
+    fn name(&self) -> &str {
+        "greedy"
+    }
+
+    fn next_move(
+        &self,
+        grid: &Grid,
+        _spawn_table: &SpawnTable,
+        _rng: &mut dyn RngCore,
+    ) -> Option<Direction> {
+        let mut best: Option<(Direction, u64)> = None;
+        for direction in corner_order(grid) {
+            let outcome = grid.clone().shift(*direction);
+            if outcome.moved() && !matches!(best, Some((_, score)) if score >= outcome.score) {
+                best = Some((*direction, outcome.score));
+            }
+        }
+        best.map(|(direction, _)| direction)
+    }
+}
+
+/// The best move of [`Solver::evaluate`].
+impl Strategy for Solver {
+    fn name(&self) -> &str {
+        "expectimax"
+    }
+
+    fn next_move(
+        &self,
+        grid: &Grid,
+        spawn_table: &SpawnTable,
+        _rng: &mut dyn RngCore,
+    ) -> Option<Direction> {
+        self.best_move(grid, spawn_table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Cell, GameRng, Tile};
+
+    // a 4x4 board with tiles of the levels at (x, y)
+    fn board(tiles: &[(u8, u8, u32)]) -> Grid {
+        let mut grid = Grid::new(4, 4);
+        for (x, y, level) in tiles {
+            grid.set(Cell { x: *x, y: *y }, Some(Tile::number(*level)));
+        }
+        grid
+    }
+
+    fn next_move(strategy: &dyn Strategy, grid: &Grid) -> Option<Direction> {
+        strategy.next_move(grid, &SpawnTable::default(), &mut GameRng::new(0))
+    }
+
+    fn dead_board() -> Grid {
+        let mut grid = Grid::new(2, 2);
+        for (x, y, level) in [(0, 0, 1), (1, 0, 2), (0, 1, 2), (1, 1, 1)] {
+            grid.set(Cell { x, y }, Some(Tile::number(level)));
+        }
+        grid
+    }
+
+    #[test]
+    fn corner_prefers_down_then_left() {
+        assert_eq!(
+            next_move(&Corner, &board(&[(1, 1, 1)])),
+            Some(Direction::Down)
+        );
+        assert_eq!(
+            next_move(&Corner, &board(&[(1, 0, 1)])),
+            Some(Direction::Left)
+        );
+        assert_eq!(
+            next_move(&Corner, &board(&[(0, 0, 1)])),
+            Some(Direction::Right)
+        );
+        assert_eq!(next_move(&Corner, &dead_board()), None);
+    }
+
+    #[test]
+    fn greedy_picks_the_highest_score() {
+        // down merges two 2s, left and right two 4s
+        let grid = board(&[(0, 3, 1), (0, 2, 1), (2, 0, 2), (3, 0, 2)]);
+        assert_eq!(next_move(&Greedy, &grid), Some(Direction::Left));
+        assert_eq!(next_move(&Corner, &grid), Some(Direction::Down));
+        // ties go to the corner order
+        assert_eq!(
+            next_move(&Greedy, &board(&[(1, 1, 1)])),
+            Some(Direction::Down)
+        );
+        let pair = board(&[(1, 0, 1), (2, 0, 1)]);
+        assert_eq!(next_move(&Greedy, &pair), Some(Direction::Left));
+        assert_eq!(next_move(&Greedy, &dead_board()), None);
+    }
+
+    #[test]
+    fn random_moves_move_tiles() {
+        let grid = board(&[(0, 0, 1)]);
+        let spawn_table = SpawnTable::default();
+        let mut rng = GameRng::new(0);
+        let moves = (0..50)
+            .map(|_| RandomMoves.next_move(&grid, &spawn_table, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(moves
+            .iter()
+            .all(|direction| matches!(direction, Some(Direction::Right | Direction::Up))));
+        assert!(moves.contains(&Some(Direction::Right)));
+        assert!(moves.contains(&Some(Direction::Up)));
+        assert_eq!(next_move(&RandomMoves, &dead_board()), None);
+    }
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use std::time::Duration;
+
+use crate::components::{Game, RunState, Settings};
+use crate::engine::{Grid, Solver, SpawnTable};
+use crate::history::{RedoEvent, UndoEvent};
+use crate::share::LoadCodeEvent;
+use crate::{board_shift, layout_offset, MoveEvent, TILE_SIZE};
+
+/// How long a hint may think before showing the best move found so far.
+const HINT_TIME_BUDGET: Duration = Duration::from_millis(100);
+
+/// Shows the best move on top of the board.
+pub struct HintEvent;
+
+/// The arrow and expected score of a hint, removed on the next move.
+#[derive(Component)]
+struct Hint;
+
+pub struct HintPlugin;
+
+impl Plugin for HintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HintEvent>()
+            .add_system_set(
+                SystemSet::on_update(RunState::Playing)
+                    .with_system(hint_input)
+                    // on the board left by this frame's move
+                    .with_system(show_hint.after(hint_input).after(clear_hint)),
+            )
+            .add_system_set(SystemSet::on_enter(RunState::Playing).with_system(remove_hint))
+            .add_system(clear_hint.after(board_shift));
+    }
+}
+
+fn hint_input(keyboard_input: Res<Input<KeyCode>>, mut hint_writer: EventWriter<HintEvent>) {
+    if keyboard_input.just_pressed(KeyCode::H) {
+        hint_writer.send(HintEvent);
+    }
+}
+
+fn show_hint(
+    mut commands: Commands,
+    mut hint_reader: EventReader<HintEvent>,
+    grid: Res<Grid>,
+    spawn_table: Res<SpawnTable>,
+    settings: Res<Settings>,
+    mut game: ResMut<Game>,
+    hints: Query<Entity, With<Hint>>,
+    asset_server: Res<AssetServer>,
+) {
+    if hint_reader.iter().count() == 0 || !hints.is_empty() {
+        return;
+    }
+    let solver = Solver::new(4)
+        .with_time_budget(HINT_TIME_BUDGET)
+        .with_spawns(settings.spawns_per_move);
+    let best = match solver.evaluate(&grid, &spawn_table).first() {
+        Some(best) => *best,
+        None => return,
+    };
+    game.hints += 1;
+
+    let (dx, dy) = grid
+        .topology()
+        .step(best.direction)
+        .expect("a direction of the topology");
+    let offset = layout_offset(grid.topology(), f32::from(dx), f32::from(dy));
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(3.0 * TILE_SIZE)),
+                color: Color::rgba(0.24, 0.23, 0.2, 0.8),
+                ..default()
+            },
+            texture: asset_server.load("sprites/arrow.png"),
+            transform: Transform::from_xyz(0.0, 0.0, 5.0)
+                .with_rotation(Quat::from_rotation_z(offset.y.atan2(offset.x))),
+            ..default()
+        })
+        .insert(Hint);
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                format!("+{:.0}", best.expected_score),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            transform: Transform::from_xyz(0.0, 0.0, 6.0),
+            ..default()
+        })
+        .insert(Hint);
+}
+
+// the hint is only good for the board it was given on, so it goes with
+// anything changing the board: moves, undos, loaded codes and new games
+fn clear_hint(
+    commands: Commands,
+    mut move_reader: EventReader<MoveEvent>,
+    mut undo_reader: EventReader<UndoEvent>,
+    mut redo_reader: EventReader<RedoEvent>,
+    mut load_reader: EventReader<LoadCodeEvent>,
+    hints: Query<Entity, With<Hint>>,
+) {
+    // reads every event, so none of them clears a later hint
+    let changes = move_reader.iter().count()
+        + undo_reader.iter().count()
+        + redo_reader.iter().count()
+        + load_reader.iter().count();
+    if changes > 0 {
+        remove_hint(commands, hints);
+    }
+}
+
+fn remove_hint(mut commands: Commands, hints: Query<Entity, With<Hint>>) {
+    for entity in hints.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+
+use std::collections::VecDeque;
+
+use crate::components::{Block, Board, Game, RunState, Settings};
+use crate::engine::{GameRng, Grid};
+use crate::{change_run_state, redraw_blocks};
+
+/// Number of moves that can be undone at most.
+const HISTORY_CAPACITY: usize = 100;
+
+pub struct UndoEvent;
+
+pub struct RedoEvent;
+
+/// Everything needed to put the game back the way it was before a move.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub grid: Grid,
+    pub rng: GameRng,
+    pub score: u64,
+    pub score_best: u64,
+    pub moves: u32,
+    pub won: bool,
+}
+
+impl Snapshot {
+    pub fn take(grid: &Grid, rng: &GameRng, game: &Game) -> Self {
+        Self {
+            grid: grid.clone(),
+            rng: *rng,
+            score: game.score,
+            score_best: game.score_best,
+            moves: game.moves,
+            won: game.won,
+        }
+    }
+
+    fn restore(self, grid: &mut Grid, rng: &mut GameRng, game: &mut Game) {
+        *grid = self.grid;
+        *rng = self.rng;
+        game.score = self.score;
+        game.score_best = self.score_best;
+        game.moves = self.moves;
+        game.won = self.won;
+    }
+}
+
+/// The moves of the running game that can be undone and redone.
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Undos used in this game, checked against [`Settings::undo_limit`].
+    pub undos: u32,
+}
+
+impl History {
+    /// Remembers the state before a move. Moves that were undone can't be
+    /// redone anymore after that.
+    pub fn record(&mut self, before: Snapshot) {
+        if self.undo.len() == HISTORY_CAPACITY {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before);
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.undos += 1;
+        Some(snapshot)
+    }
+
+    fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(snapshot)
+    }
+}
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_event::<UndoEvent>()
+            .add_event::<RedoEvent>()
+            .add_system(history_input)
+            // after the blocks of a move in the same frame have been spawned,
+            // so they get cleared too
+            .add_system_to_stage(CoreStage::PostUpdate, undo_redo);
+    }
+}
+
+fn history_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut undo_writer: EventWriter<UndoEvent>,
+    mut redo_writer: EventWriter<RedoEvent>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Z) {
+        undo_writer.send(UndoEvent);
+    } else if keyboard_input.just_pressed(KeyCode::Y) {
+        redo_writer.send(RedoEvent);
+    }
+}
+
+fn undo_redo(
+    mut commands: Commands,
+    mut undo_reader: EventReader<UndoEvent>,
+    mut redo_reader: EventReader<RedoEvent>,
+    mut history: ResMut<History>,
+    mut grid: ResMut<Grid>,
+    mut game_rng: ResMut<GameRng>,
+    mut game: ResMut<Game>,
+    settings: Res<Settings>,
+    mut run_state: ResMut<State<RunState>>,
+    blocks: Query<Entity, With<Block>>,
+    query_board: Query<&Board>,
+    asset_server: Res<AssetServer>,
+) {
+    let undo = undo_reader.iter().count() > 0;
+    let redo = redo_reader.iter().count() > 0;
+    if !undo && !redo {
+        return;
+    }
+    let game_over = match run_state.current() {
+        RunState::Playing => false,
+        // the move that lost the game can be undone too, but a game ended
+        // with moves left stays over
+        RunState::GameOver if !grid.has_moves() => true,
+        _ => return,
+    };
+
+    let current = Snapshot::take(&grid, &game_rng, &game);
+    let snapshot = if undo {
+        if settings
+            .undo_limit
+            .map_or(false, |limit| history.undos >= limit)
+        {
+            return;
+        }
+        history.undo(current)
+    } else {
+        history.redo(current)
+    };
+
+    if let Some(snapshot) = snapshot {
+        snapshot.restore(&mut grid, &mut game_rng, &mut game);
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        redraw_blocks(
+            &mut commands,
+            &font,
+            query_board.single(),
+            &grid,
+            blocks.iter(),
+        );
+        // popping `GameOver` resumes the game instead of starting a new one
+        if game_over && grid.has_moves() {
+            change_run_state(run_state.pop());
+        } else if !game_over && !grid.has_moves() {
+            change_run_state(run_state.push(RunState::GameOver));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(moves: u32) -> Snapshot {
+        Snapshot {
+            grid: Grid::new(4, 4),
+            rng: GameRng::new(0),
+            score: 0,
+            score_best: 0,
+            moves,
+            won: false,
+        }
+    }
+
+    #[test]
+    fn record_forgets_the_oldest_moves() {
+        let mut history = History::default();
+        for moves in 0..HISTORY_CAPACITY as u32 + 5 {
+            history.record(snapshot(moves));
+        }
+        let current = HISTORY_CAPACITY as u32 + 5;
+        let undone: Vec<_> = std::iter::from_fn(|| history.undo(snapshot(current)))
+            .map(|snapshot| snapshot.moves)
+            .collect();
+        assert_eq!(undone.len(), HISTORY_CAPACITY);
+        assert_eq!(undone.first(), Some(&(current - 1)));
+        assert_eq!(undone.last(), Some(&5));
+    }
+
+    #[test]
+    fn record_clears_redo() {
+        let mut history = History::default();
+        history.record(snapshot(0));
+        history.record(snapshot(1));
+        assert_eq!(history.undo(snapshot(2)).unwrap().moves, 1);
+        assert_eq!(history.redo(snapshot(1)).unwrap().moves, 2);
+        assert!(history.redo(snapshot(2)).is_none());
+        assert_eq!(history.undo(snapshot(2)).unwrap().moves, 1);
+        history.record(snapshot(1));
+        assert!(history.redo(snapshot(2)).is_none());
+        assert_eq!(history.undo(snapshot(2)).unwrap().moves, 1);
+    }
+
+    #[test]
+    fn undos_count_undone_moves() {
+        let mut history = History::default();
+        history.record(snapshot(0));
+        assert!(history.redo(snapshot(1)).is_none());
+        history.undo(snapshot(1));
+        history.redo(snapshot(0));
+        history.undo(snapshot(1));
+        assert!(history.undo(snapshot(0)).is_none());
+        assert_eq!(history.undos, 2);
+        history.clear();
+        assert_eq!(history.undos, 0);
+        assert!(history.undo(snapshot(0)).is_none());
+    }
+}
@@ -1,50 +1,102 @@
+use bevy::ecs::schedule::StateError;
 use bevy::prelude::*;
 use bevy_easings::*;
 use itertools::Itertools;
-use rand::prelude::*;
 
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::ops::Range;
+use std::time::Duration;
 
+mod autoplay;
 mod components;
+pub mod engine;
+mod hint;
+mod history;
+mod share;
 mod ui;
 
+use autoplay::AutoplayPlugin;
 use components::*;
+use engine::{Cell, Direction, GameRng, Grid, MoveOutcome, SpawnTable, Topology};
+use hint::HintPlugin;
+use history::{History, HistoryPlugin, Snapshot};
+use share::SharePlugin;
 use ui::*;
 
+pub use autoplay::AutoplaySpeed;
+pub use components::Settings;
+
 const TILE_SPACER: f32 = 10.0;
 const TILE_SIZE: f32 = 60.0;
 
-pub struct NewTileEvent;
+// touches moving less than this many pixels are taps, e.g. on buttons, which
+// neither move the tiles nor stop autoplay
+const SWIPE_MIN_DISTANCE: f32 = 20.0;
+
+/// Sent after every move with everything that happened to the board.
+pub struct MoveEvent(pub MoveOutcome);
+
+/// Plays a move as if it came from the keyboard or a swipe.
+pub struct MoveRequestEvent(pub Direction);
+
+/// Sent when a move was rejected because no tile could move that way.
+pub struct BlockedMoveEvent(pub Direction);
+
+/// Sent once per game, when a tile reaches the win level.
+pub struct WinEvent {
+    pub level: u32,
+    pub score: u64,
+}
+
+/// Sent when a game ends, either because no move is left or because the
+/// player ended it.
+pub struct GameOverEvent {
+    pub score: u64,
+    /// Level of the highest tile on the board.
+    pub max_level: Option<u32>,
+    pub moves: u32,
+    pub hints: u32,
+    pub duration: Duration,
+}
 
 struct Palette {
     board: Color,
+    board_blocked: Color,
     tile_placeholder: Color,
+    wall: Color,
 }
 
 #[bevy_main]
 pub fn main() {
+    run(Settings::default());
+}
+
+pub fn run(settings: Settings) {
     App::new()
         .insert_resource(WindowDescriptor {
             title: "Yars 2048".to_string(),
             ..default()
         })
         .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.1)))
+        .insert_resource(settings.spawn_table.clone())
+        .insert_resource(GameRng::new(settings.seed.unwrap_or_else(rand::random)))
+        .insert_resource(settings)
         .init_resource::<Game>()
         .add_startup_system(setup)
         // .add_startup_system(setup_ui)
         .add_plugins(DefaultPlugins)
         .add_plugin(GameUiPlugin)
+        .add_plugin(HistoryPlugin)
+        .add_plugin(SharePlugin)
+        .add_plugin(HintPlugin)
+        .add_plugin(AutoplayPlugin)
         .add_plugin(bevy_easings::EasingsPlugin)
         .add_startup_stage("board_setup", SystemStage::single(spawn_board))
         .add_state(RunState::Playing)
         .add_system_set(
             SystemSet::on_update(RunState::Playing)
                 .with_system(board_shift)
-                .with_system(render_blocks)
-                .with_system(render_block_color)
-                .with_system(new_tile_handler),
+                .with_system(render_blocks.after(board_shift))
+                .with_system(render_block_color.after(render_blocks))
+                .with_system(render_blocked_move.after(board_shift)),
         )
         // setup when entering the state
         .add_system_set(
@@ -52,7 +104,12 @@ pub fn main() {
                 .with_system(game_reset)
                 .with_system(spawn_tiles.after(game_reset)),
         )
-        .add_event::<NewTileEvent>()
+        .add_system_set(SystemSet::on_enter(RunState::GameOver).with_system(game_over))
+        .add_event::<MoveRequestEvent>()
+        .add_event::<MoveEvent>()
+        .add_event::<BlockedMoveEvent>()
+        .add_event::<WinEvent>()
+        .add_event::<GameOverEvent>()
         .run();
 }
 
@@ -62,665 +119,538 @@ fn setup(mut commands: Commands) {
 
     commands.insert_resource(Palette {
         board: Color::rgb_u8(187, 173, 160),
+        board_blocked: Color::rgb_u8(214, 120, 100),
         tile_placeholder: Color::rgb_u8(214, 205, 196),
+        wall: Color::rgb_u8(119, 110, 101),
     });
 }
 
-fn spawn_board(mut commands: Commands, palette: Res<Palette>) {
-    let board = Board { size: 4 };
-    let physical_board_size = {
+fn spawn_board(
+    mut commands: Commands,
+    palette: Res<Palette>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    let (width, height) = match settings.topology {
+        Topology::Square | Topology::Torus => {
+            let sizes = Settings::BOARD_SIZES;
+            (
+                settings.board_width.clamp(*sizes.start(), *sizes.end()),
+                settings.board_height.clamp(*sizes.start(), *sizes.end()),
+            )
+        }
+        Topology::Hex => {
+            let sides = Settings::HEX_SIDES;
+            let side = settings.board_width.clamp(*sides.start(), *sides.end());
+            (2 * side - 1, 2 * side - 1)
+        }
+    };
+    let mut board = Board {
+        width,
+        height,
+        walls: Vec::new(),
+        ruleset: settings.ruleset.clone(),
+        topology: settings.topology,
+        tile_texture: tile_texture(&asset_server, settings.topology),
+    };
+    let grid = board.grid();
+    board.walls = settings
+        .walls
+        .iter()
+        .filter(|wall| grid.contains(**wall))
+        .copied()
+        .collect();
+    commands.insert_resource(board.grid());
+    spawn_board_sprite(&mut commands, &palette, &asset_server, board);
+}
+
+// the shape of the tiles on boards of `topology`, see `Board::tile_texture`
+fn tile_texture(asset_server: &AssetServer, topology: Topology) -> Option<Handle<Image>> {
+    (topology == Topology::Hex).then(|| asset_server.load("sprites/hex_tile.png"))
+}
+
+// spawns the board sprite with its placeholders, holding `board`
+fn spawn_board_sprite(
+    commands: &mut Commands,
+    palette: &Palette,
+    asset_server: &AssetServer,
+    board: Board,
+) {
+    let grid = board.grid();
+    let physical_board_size = |tiles: u8| {
         // size of all tiles
-        f32::from(board.size) * TILE_SIZE
+        f32::from(tiles) * TILE_SIZE
         // size of all spacers
-        + f32::from(board.size) * TILE_SPACER
+        + f32::from(tiles) * TILE_SPACER
         // extra spacer on the off side to round out the board
         + TILE_SPACER
     };
+    let (board_size, board_texture) = match board.topology {
+        Topology::Square | Topology::Torus => (
+            Vec2::new(
+                physical_board_size(board.width),
+                physical_board_size(board.height),
+            ),
+            None,
+        ),
+        Topology::Hex => {
+            // a pointy topped hexagon reaching one tile past the corner tiles
+            let radius = f32::from(board.width / 2 + 1) * (TILE_SIZE + TILE_SPACER);
+            (
+                Vec2::new(3f32.sqrt() * radius, 2.0 * radius),
+                Some(asset_server.load("sprites/hex_board.png")),
+            )
+        }
+    };
 
-    commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(physical_board_size, physical_board_size)),
-                color: palette.board,
-                ..default()
-            },
+    let mut board_sprite = SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(board_size),
+            color: palette.board,
             ..default()
-        })
+        },
+        ..default()
+    };
+    if let Some(texture) = board_texture {
+        board_sprite.texture = texture;
+    }
+    commands
+        .spawn_bundle(board_sprite)
         .with_children(|child_builder| {
-            for tile in (0..board.size).cartesian_product(0..board.size) {
+            let cells = (0..board.width)
+                .cartesian_product(0..board.height)
+                .filter(|(x, y)| grid.contains(Cell { x: *x, y: *y }));
+            for tile in cells {
                 let position = Position {
                     x: tile.0,
                     y: tile.1,
                 };
-                child_builder
-                    .spawn_bundle(SpriteBundle {
-                        sprite: Sprite {
-                            custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-                            color: palette.tile_placeholder,
-                            ..default()
-                        },
-                        transform: Transform::from_xyz(
-                            // true position
-                            f32::from(tile.0) * TILE_SIZE
-                        // moved left because it is at board center
-                            - (f32::from(board.size)
-                                * TILE_SIZE
-                                / 2.0)
-                                // moved right because it's even numbered
-                                // (odd would be centered)
-                            + (0.5 * TILE_SIZE)
-                            // account for in-between spacing by applying N
-                            // spacers
-                            + f32::from(tile.0)
-                                * TILE_SPACER
-                                - TILE_SPACER * 1.5,
-                            f32::from(tile.1) * TILE_SIZE
-                                - (f32::from(board.size) * TILE_SIZE / 2.0)
-                                + (0.5 * TILE_SIZE)
-                                + f32::from(tile.1) * TILE_SPACER
-                                - TILE_SPACER * 1.5,
-                            1.0,
-                        ),
+                let is_wall = board.walls.contains(&position.into());
+                let color = if is_wall {
+                    palette.wall
+                } else {
+                    palette.tile_placeholder
+                };
+                let mut placeholder =
+                    child_builder.spawn_bundle(tile_sprite(&board, position, color));
+                placeholder.insert(position);
+                if is_wall {
+                    placeholder.insert(Wall);
+                } else {
+                    placeholder.insert(EmptyBlock);
+                }
+            }
+
+            if board.topology == Topology::Torus {
+                // notches on both ends of every line, showing where lines
+                // come back on the board
+                let notch = |size: Vec2, translation: Vec2| SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        color: palette.tile_placeholder,
                         ..default()
-                    })
-                    .insert(position)
-                    .insert(EmptyBlock);
+                    },
+                    transform: Transform::from_translation(translation.extend(1.0)),
+                    ..default()
+                };
+                let edge = board_size / 2.0 + TILE_SPACER / 2.0;
+                for y in 0..board.height {
+                    let row = block_pos_to_translation(&board, Position { x: 0, y }).y;
+                    let size = Vec2::new(TILE_SPACER, TILE_SIZE / 2.0);
+                    child_builder.spawn_bundle(notch(size, Vec2::new(-edge.x, row)));
+                    child_builder.spawn_bundle(notch(size, Vec2::new(edge.x, row)));
+                }
+                for x in 0..board.width {
+                    let column = block_pos_to_translation(&board, Position { x, y: 0 }).x;
+                    let size = Vec2::new(TILE_SIZE / 2.0, TILE_SPACER);
+                    child_builder.spawn_bundle(notch(size, Vec2::new(column, -edge.y)));
+                    child_builder.spawn_bundle(notch(size, Vec2::new(column, edge.y)));
+                }
             }
         })
         .insert(board);
 }
 
-fn game_reset(mut commands: Commands, blocks: Query<Entity, With<Block>>, mut game: ResMut<Game>) {
+fn game_reset(
+    mut commands: Commands,
+    blocks: Query<Entity, With<Block>>,
+    query_board: Query<&Board>,
+    settings: Res<Settings>,
+    mut grid: ResMut<Grid>,
+    mut game_rng: ResMut<GameRng>,
+    mut history: ResMut<History>,
+    mut game: ResMut<Game>,
+    time: Res<Time>,
+) {
+    history.clear();
     for entity in blocks.iter() {
         commands.entity(entity).despawn_recursive();
     }
+    *grid = query_board.single().grid();
+    *game_rng = GameRng::new(settings.seed.unwrap_or_else(rand::random));
     game.score = 0;
+    game.won = false;
+    game.moves = 0;
+    game.hints = 0;
+    game.started = time.seconds_since_startup();
+}
+
+fn game_over(
+    game: Res<Game>,
+    grid: Res<Grid>,
+    time: Res<Time>,
+    mut game_over_writer: EventWriter<GameOverEvent>,
+) {
+    game_over_writer.send(GameOverEvent {
+        score: game.score,
+        max_level: grid.max_level(),
+        moves: game.moves,
+        hints: game.hints,
+        duration: Duration::from_secs_f64(time.seconds_since_startup() - game.started),
+    });
 }
 
-fn spawn_tiles(mut commands: Commands, query_board: Query<&Board>, asset_server: Res<AssetServer>) {
+fn spawn_tiles(
+    mut commands: Commands,
+    query_board: Query<&Board>,
+    mut grid: ResMut<Grid>,
+    spawn_table: Res<SpawnTable>,
+    mut game_rng: ResMut<GameRng>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     let board = query_board.single();
-    // insert new tile
-    let mut rng = rand::thread_rng();
-    let starting_tiles: Vec<Position> = (0..board.size)
-        .cartesian_product(0..board.size)
-        .map(|tile_pos| Position {
-            x: tile_pos.0,
-            y: tile_pos.1,
-        })
-        .choose_multiple(&mut rng, 2);
-    for Position { x, y } in starting_tiles.iter() {
-        let pos = Position { x: *x, y: *y };
-        let new_block = Block::default();
-        commands
-            .spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-                    color: new_block.color(),
-                    ..default()
-                },
-                transform: Transform::from_xyz(
-                    block_pos_to_transform(board.size, pos.x),
-                    block_pos_to_transform(board.size, pos.y),
-                    1.0,
-                ),
-                ..default()
-            })
-            .with_children(|child_builder| {
-                child_builder
-                    .spawn_bundle(Text2dBundle {
-                        text: Text::with_section(
-                            new_block.score().to_string(),
-                            TextStyle {
-                                font: font.clone(),
-                                font_size: 40.0,
-                                color: Color::BLACK,
-                            },
-                            TextAlignment {
-                                vertical: VerticalAlign::Center,
-                                horizontal: HorizontalAlign::Center,
-                            },
-                        ),
-                        transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                        ..default()
-                    })
-                    .insert(BlockText);
-            })
-            .insert(new_block)
-            .insert(pos);
+    // the opening tiles
+    for _ in 0..settings.opening_tiles {
+        if let Some(spawn) = grid.spawn(&spawn_table, &mut *game_rng) {
+            let block = Block::from(spawn.tile);
+            spawn_block(&mut commands, &font, board, spawn.cell.into(), block);
+        }
     }
 }
 
-fn block_pos_to_transform(board_size: u8, pos: u8) -> f32 {
-    f32::from(pos) * TILE_SIZE
-        // moved left because it is at board center
-            - (f32::from(board_size)
-                * TILE_SIZE
-                / 2.0)
-                // moved right because it's even numbered
-                // (odd would be centered)
-            + (0.5 * TILE_SIZE)
-            // account for in-between spacing by applying N
-            // spacers
-            + f32::from(pos)
-                * TILE_SPACER
-        - TILE_SPACER * 1.5
+fn spawn_block(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    board: &Board,
+    pos: Position,
+    new_block: Block,
+) {
+    commands
+        .spawn_bundle(tile_sprite(board, pos, new_block.color(&*board.ruleset)))
+        .with_children(|child_builder| {
+            child_builder
+                .spawn_bundle(Text2dBundle {
+                    text: Text::with_section(
+                        new_block.label(&*board.ruleset),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: new_block.font_size(&*board.ruleset),
+                            color: new_block.text_color(&*board.ruleset),
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                    ..default()
+                })
+                .insert(BlockText);
+        })
+        .insert(new_block)
+        .insert(pos);
 }
 
-fn render_blocks(
-    mut commands: Commands,
-    mut blocks: Query<(Entity, &mut Transform, &Position, Changed<Position>), With<Block>>,
-    query_board: Query<&Board>,
+// despawn every block and spawn them again from the grid,
+// for changes of the board that aren't moves
+fn redraw_blocks(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    board: &Board,
+    grid: &Grid,
+    blocks: impl Iterator<Item = Entity>,
 ) {
-    let board = query_board.single();
-    for (entity, transform, pos, pos_changed) in blocks.iter_mut() {
-        if pos_changed {
-            let x = block_pos_to_transform(board.size, pos.x);
-            let y = block_pos_to_transform(board.size, pos.y);
-            let mut ent = commands.entity(entity);
-            ent.insert(transform.ease_to(
-                Transform::from_xyz(x, y, transform.translation.z),
-                EaseFunction::QuadraticInOut,
-                EasingType::Once {
-                    duration: std::time::Duration::from_millis(100),
-                },
-            ));
-        }
+    for entity in blocks {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (cell, tile) in grid.tiles() {
+        spawn_block(commands, font, board, cell.into(), tile.into());
     }
 }
 
-fn render_block_color(mut block_sprite: Query<(&mut Sprite, &Block), With<Block>>) {
-    for (mut sp, block) in block_sprite.iter_mut() {
-        sp.color = block.color();
+// the sprite of a tile or of a placeholder at `pos`
+fn tile_sprite(board: &Board, pos: Position, color: Color) -> SpriteBundle {
+    let mut sprite = SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(tile_size(board.topology)),
+            color,
+            ..default()
+        },
+        transform: Transform::from_translation(block_pos_to_translation(board, pos).extend(1.0)),
+        ..default()
+    };
+    if let Some(texture) = &board.tile_texture {
+        sprite.texture = texture.clone();
     }
+    sprite
 }
 
-#[derive(Debug)]
-enum MergeStatus {
-    Merge,
-    DifferentRows,
-    DifferentValues,
+fn tile_size(topology: Topology) -> Vec2 {
+    match topology {
+        Topology::Square | Topology::Torus => Vec2::new(TILE_SIZE, TILE_SIZE),
+        // flat topped, TILE_SIZE high
+        Topology::Hex => Vec2::new(2.0 / 3f32.sqrt() * TILE_SIZE, TILE_SIZE),
+    }
 }
-// u32 is the block value
-// u8 is the "vertical" y position,
-// which is different for each direction
-fn should_merge(block: (u32, u8), block_next: (u32, u8)) -> MergeStatus {
-    if block.1 != block_next.1 {
-        // if the blocks aren't on the same vertical level
-        // they can't collide, so skip to next loop
-        // iteration
-        MergeStatus::DifferentRows
-    } else if block.0 != block_next.0 {
-        // if the block values don't match,
-        // they can't merge, so skip to the
-        // next loop iteration
-        MergeStatus::DifferentValues
-    } else {
-        MergeStatus::Merge
+
+// offset between the centers of two tiles `dx` columns and `dy` rows apart
+fn layout_offset(topology: Topology, dx: f32, dy: f32) -> Vec2 {
+    // distance between the centers of two neighbouring tiles
+    let step = TILE_SIZE + TILE_SPACER;
+    match topology {
+        Topology::Square | Topology::Torus => Vec2::new(dx, dy) * step,
+        // columns of hexagons interlock, each one half a tile higher than
+        // the one on its left
+        Topology::Hex => Vec2::new(dx * 3f32.sqrt() / 2.0, dy + dx / 2.0) * step,
     }
 }
 
-fn board_shift(
+fn block_pos_to_translation(board: &Board, pos: Position) -> Vec2 {
+    // moved back by half the board because the board is centered (tiles of
+    // odd sized boards have one tile on the center)
+    layout_offset(
+        board.topology,
+        f32::from(pos.x) - f32::from(board.width - 1) / 2.0,
+        f32::from(pos.y) - f32::from(board.height - 1) / 2.0,
+    )
+}
+
+// mirror the outcome of each move onto the block entities
+fn render_blocks(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
-    touches: Res<Touches>,
-    // mut query_world: Query<&mut World>,
-    mut texts: Query<(Entity, &mut Text), With<BlockText>>,
-    mut blocks: Query<(Entity, &mut Position, &mut Block, &Children)>,
+    mut move_reader: EventReader<MoveEvent>,
+    mut texts: Query<&mut Text, With<BlockText>>,
+    mut blocks: Query<(Entity, &Transform, &mut Position, &mut Block, &Children)>,
     query_board: Query<&Board>,
-    mut tile_writer: EventWriter<NewTileEvent>,
-    mut game: ResMut<Game>,
-    mut run_state: ResMut<State<RunState>>,
+    asset_server: Res<AssetServer>,
 ) {
-    // Normal Processing
     let board = query_board.single();
-
-    // EndGameCheck
-    if blocks.iter_mut().len() == 16 {
-        let mut map: HashMap<(u8, u8), u32> = HashMap::new();
-        for tile in (0..board.size).cartesian_product(0..board.size) {
-            map.insert(tile, 0);
-        }
-        for (_, position, block, _) in blocks.iter_mut() {
-            map.insert((position.x, position.y), block.level);
+    for MoveEvent(outcome) in move_reader.iter() {
+        for (entity, transform, mut position, mut block, children) in blocks.iter_mut() {
+            let cell = Cell::from(*position);
+            let to = if let Some(slide) = outcome.slides.iter().find(|s| s.from == cell) {
+                slide.to
+            } else if let Some(merge) = outcome.merges.iter().find(|m| m.from[0] == cell) {
+                *block = merge.tile.into();
+                // update text
+                for child in children.iter() {
+                    let mut text = texts.get_mut(*child).expect("text to exist");
+                    let section = text
+                        .sections
+                        .first_mut()
+                        .expect("expect a single section in text");
+                    section.value = block.label(&*board.ruleset);
+                    section.style.font_size = block.font_size(&*board.ruleset);
+                    section.style.color = block.text_color(&*board.ruleset);
+                }
+                merge.into
+            } else if outcome.merges.iter().any(|m| m.from[1..].contains(&cell)) {
+                // merged into another block
+                commands.entity(entity).despawn_recursive();
+                continue;
+            } else {
+                continue;
+            };
+
+            if cell != to {
+                *position = to.into();
+                let translation = block_pos_to_translation(board, *position);
+                let target =
+                    Transform::from_translation(translation.extend(transform.translation.z));
+                if wraps(board.topology, outcome.direction, cell, to) {
+                    // sliding across the whole board would look like a move
+                    // the other way
+                    commands.entity(entity).insert(target);
+                } else {
+                    commands.entity(entity).insert(transform.ease_to(
+                        target,
+                        EaseFunction::QuadraticInOut,
+                        EasingType::Once {
+                            duration: Duration::from_millis(100),
+                        },
+                    ));
+                }
+            }
         }
-        let has_move = map.iter().any(|((x, y), value)| {
-            vec![(-1, 0), (0, 1), (1, 0), (0, -1)]
-                .iter()
-                .flat_map(|(x2, y2)| {
-                    let new_x = (*x as i8) - x2;
-                    let new_y = (*y as i8) - y2;
-
-                    let board_range: Range<i8> = 0..(board.size as i8);
-
-                    if !board_range.contains(&new_x) && !board_range.contains(&new_y) {
-                        return None;
-                    };
-
-                    match (new_x.try_into(), new_y.try_into()) {
-                        (Ok(x), Ok(y)) => Some(map.get(&(x, y))),
-                        _ => None,
-                    }
-                })
-                .flatten()
-                .any(|v| v == value)
-        });
 
-        if !has_move {
-            run_state.set(RunState::GameOver).unwrap();
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        for spawn in &outcome.spawned {
+            let block = Block::from(spawn.tile);
+            spawn_block(&mut commands, &font, board, spawn.cell.into(), block);
         }
-    };
-
-    let touch_event = touches.iter_just_released().next();
+    }
+}
 
-    let move_left = {
-        keyboard_input.just_pressed(KeyCode::Left)
-            || touch_event.map_or(false, |t| {
-                let a = t.distance().to_array();
-                a[0].is_sign_negative() && a[0].abs() > a[1].abs()
-            })
+// whether a tile went from `from` to `to` across the seam of a torus
+fn wraps(topology: Topology, direction: Direction, from: Cell, to: Cell) -> bool {
+    let (dx, dy) = match topology.step(direction) {
+        Some(step) if topology == Topology::Torus => step,
+        _ => return false,
     };
+    let moved_x = i16::from(to.x) - i16::from(from.x);
+    let moved_y = i16::from(to.y) - i16::from(from.y);
+    moved_x * i16::from(dx) + moved_y * i16::from(dy) < 0
+}
 
-    let move_right = {
-        keyboard_input.just_pressed(KeyCode::Right)
-            || touch_event.map_or(false, |t| {
-                let a = t.distance().to_array();
-                a[0].is_sign_positive() && a[0].abs() > a[1].abs()
-            })
-    };
+// flash the board when a move didn't change anything
+fn render_blocked_move(
+    mut commands: Commands,
+    mut blocked_reader: EventReader<BlockedMoveEvent>,
+    query_board: Query<(Entity, &Sprite), With<Board>>,
+    palette: Res<Palette>,
+) {
+    if blocked_reader.iter().next().is_some() {
+        let (entity, sprite) = query_board.single();
+        commands.entity(entity).insert(
+            Sprite {
+                color: palette.board_blocked,
+                ..sprite.clone()
+            }
+            .ease_to(
+                Sprite {
+                    color: palette.board,
+                    ..sprite.clone()
+                },
+                EaseFunction::QuadraticOut,
+                EasingType::Once {
+                    duration: Duration::from_millis(200),
+                },
+            ),
+        );
+    }
+}
 
-    let move_down = {
-        keyboard_input.just_pressed(KeyCode::Down)
-            || touch_event.map_or(false, |t| {
-                let a = t.distance().to_array();
-                a[1].is_sign_negative() && a[1].abs() > a[0].abs()
-            })
-    };
+fn render_block_color(
+    mut block_sprite: Query<(&mut Sprite, &Block), With<Block>>,
+    query_board: Query<&Board>,
+) {
+    let board = query_board.single();
+    for (mut sp, block) in block_sprite.iter_mut() {
+        sp.color = block.color(&*board.ruleset);
+    }
+}
 
-    let move_up = {
-        keyboard_input.just_pressed(KeyCode::Up)
-            || touch_event.map_or(false, |t| {
-                let a = t.distance().to_array();
-                a[1].is_sign_positive() && a[1].abs() > a[0].abs()
-            })
+fn key_direction(keyboard_input: &Input<KeyCode>, topology: Topology) -> Option<Direction> {
+    let keys: &[(KeyCode, Direction)] = match topology {
+        Topology::Square | Topology::Torus => &[
+            (KeyCode::Left, Direction::Left),
+            (KeyCode::Right, Direction::Right),
+            (KeyCode::Down, Direction::Down),
+            (KeyCode::Up, Direction::Up),
+        ],
+        // the keys around S, or up and down arrows
+        Topology::Hex => &[
+            (KeyCode::Q, Direction::UpLeft),
+            (KeyCode::W, Direction::Up),
+            (KeyCode::E, Direction::UpRight),
+            (KeyCode::A, Direction::DownLeft),
+            (KeyCode::S, Direction::Down),
+            (KeyCode::D, Direction::DownRight),
+            (KeyCode::Up, Direction::Up),
+            (KeyCode::Down, Direction::Down),
+        ],
     };
+    keys.iter()
+        .find(|(key, _)| keyboard_input.just_pressed(*key))
+        .map(|(_, direction)| *direction)
+}
 
-    if move_left {
-        let mut it = blocks
-            .iter_mut()
-            .sorted_by(|a, b| match Ord::cmp(&a.1.y, &b.1.y) {
-                std::cmp::Ordering::Equal => Ord::cmp(&a.1.x, &b.1.x),
-                ordering => ordering,
-            })
-            // .sorted_by_key(|v| v.1.y)
-            .peekable();
-        let mut x: u8 = 0;
-
-        loop {
-            match (it.next(), it.peek()) {
-                (None, _) => {
-                    // nothing left to process
-                    break;
-                }
-                (Some(mut block), None) => {
-                    block.1.x = x;
-                }
-                (Some(mut block), Some(block_next)) => {
-                    match should_merge(
-                        (block.2.level, block.1.y),
-                        (block_next.2.level, block_next.1.y),
-                    ) {
-                        MergeStatus::Merge => {
-                            // despawn the next block, and
-                            // merge it with the current
-                            // block.
-                            let real_next_block = it
-                                .next()
-                                .expect("A peeked block should always exist when we .next here");
-                            block.2.level += 1;
-                            block.1.x = x;
-
-                            // update score
-                            game.score += block.2.score();
-                            // update text
-                            for child in block.3.iter() {
-                                let mut text = texts.get_mut(*child).expect("text to exist");
-                                let mut section = text
-                                    .1
-                                    .sections
-                                    .first_mut()
-                                    .expect("expect a single section in text");
-                                section.value = block.2.score().to_string();
-                            }
-                            // if the next, next block
-                            // (block #3 of 3)
-                            // isn't in the same row, reset
-                            // x
-                            // otherwise increment by one
-                            if let Some(future) = it.peek() {
-                                if block.1.y != future.1.y {
-                                    x = 0;
-                                } else {
-                                    x += 1;
-                                }
-                            }
-
-                            commands.entity(real_next_block.0).despawn_recursive();
-                            continue;
-                        }
-                        MergeStatus::DifferentRows => {
-                            block.1.x = x;
-                            x = 0;
-                            continue;
-                        }
-                        MergeStatus::DifferentValues => {
-                            block.1.x = x;
-                            x += 1;
-                            continue;
-                        }
-                    }
-                }
-            }
-            break;
-        }
-        // insert new block
-        tile_writer.send(NewTileEvent);
-    } else if move_right {
-        let mut it = blocks
-            .iter_mut()
-            // we want our sorting to first sort by x,
-            // then break x ties with y's comparison
-            .sorted_by(|a, b| match Ord::cmp(&b.1.y, &a.1.y) {
-                std::cmp::Ordering::Equal => Ord::cmp(&b.1.x, &a.1.x),
-                a => a,
-            })
-            .peekable();
-        let mut x: u8 = 0;
-        loop {
-            match (it.next(), it.peek()) {
-                (None, _) => {
-                    break;
-                }
-                (Some(mut block), None) => {
-                    block.1.x = board.size - 1 - x;
-                }
-                (Some(mut block), Some(block_next)) => {
-                    match should_merge(
-                        (block.2.level, block.1.y),
-                        (block_next.2.level, block_next.1.y),
-                    ) {
-                        MergeStatus::Merge => {
-                            let real_next_block = it
-                                .next()
-                                .expect("A peeked block should always exist when we .next here");
-                            block.2.level += 1;
-                            block.1.x = board.size - 1 - x;
-
-                            // update score
-                            game.score += block.2.score();
-                            // update text
-                            for child in block.3.iter() {
-                                let mut text = texts.get_mut(*child).expect("text to exist");
-                                let mut section = text
-                                    .1
-                                    .sections
-                                    .first_mut()
-                                    .expect("expect a single section in text");
-                                section.value = block.2.score().to_string();
-                            }
-
-                            if let Some(future) = it.peek() {
-                                if block.1.y != future.1.y {
-                                    x = 0;
-                                } else {
-                                    x += 1;
-                                }
-                            }
-                            commands.entity(real_next_block.0).despawn_recursive();
-                            continue;
-                        }
-                        MergeStatus::DifferentRows => {
-                            block.1.x = board.size - 1 - x;
-                            x = 0;
-                            continue;
-                        }
-                        MergeStatus::DifferentValues => {
-                            block.1.x = board.size - 1 - x;
-                            x += 1;
-                            continue;
-                        }
-                    }
-                }
-            }
+// the direction closest to the angle of a swipe
+fn swipe_direction(topology: Topology, swipe: Vec2) -> Option<Direction> {
+    if swipe.length() < SWIPE_MIN_DISTANCE {
+        return None;
+    }
+    let alignment = |direction: &Direction| {
+        let (dx, dy) = topology
+            .step(*direction)
+            .expect("a direction of the topology");
+        let offset = layout_offset(topology, f32::from(dx), f32::from(dy));
+        swipe.angle_between(offset).abs()
+    };
+    topology
+        .directions()
+        .iter()
+        .min_by(|a, b| alignment(a).total_cmp(&alignment(b)))
+        .copied()
+}
 
-            break;
-        }
-        // insert new block
-        tile_writer.send(NewTileEvent);
-    } else if move_down {
-        let mut it = blocks
-            .iter_mut()
-            .sorted_by(|a, b| match Ord::cmp(&a.1.x, &b.1.x) {
-                std::cmp::Ordering::Equal => Ord::cmp(&a.1.y, &b.1.y),
-                ordering => ordering,
-            })
-            .peekable();
-        let mut y: u8 = 0;
-
-        loop {
-            match (it.next(), it.peek()) {
-                (None, _) => {
-                    // nothing left to process
-                    break;
-                }
-                (Some(mut block), None) => {
-                    block.1.y = y;
-                }
-                (Some(mut block), Some(block_next)) => {
-                    match should_merge(
-                        (block.2.level, block.1.x),
-                        (block_next.2.level, block_next.1.x),
-                    ) {
-                        MergeStatus::Merge => {
-                            // _recursive the next block,
-                            // and
-                            // merge it with the current
-                            // block.
-                            let real_next_block = it
-                                .next()
-                                .expect("A peeked block should always exist when we .next here");
-                            block.2.level += 1;
-                            block.1.y = y;
-
-                            // update score
-                            game.score += block.2.score();
-                            // update text
-                            for child in block.3.iter() {
-                                let mut text = texts.get_mut(*child).expect("text to exist");
-                                let mut section = text
-                                    .1
-                                    .sections
-                                    .first_mut()
-                                    .expect("expect a single section in text");
-                                section.value = block.2.score().to_string();
-                            }
-                            // if the next, next block
-                            // (block #3 of 3)
-                            // isn't in the same row, reset
-                            // x
-                            // otherwise increment by one
-                            if let Some(future) = it.peek() {
-                                if block.1.x != future.1.x {
-                                    y = 0;
-                                } else {
-                                    y += 1;
-                                }
-                            }
-                            commands.entity(real_next_block.0).despawn_recursive();
-                            continue;
-                        }
-                        MergeStatus::DifferentRows => {
-                            block.1.y = y;
-                            y = 0;
-                            continue;
-                        }
-                        MergeStatus::DifferentValues => {
-                            block.1.y = y;
-                            y += 1;
-                            continue;
-                        }
+fn board_shift(
+    keyboard_input: Res<Input<KeyCode>>,
+    touches: Res<Touches>,
+    mut move_requests: EventReader<MoveRequestEvent>,
+    mut grid: ResMut<Grid>,
+    spawn_table: Res<SpawnTable>,
+    mut game_rng: ResMut<GameRng>,
+    mut move_writer: EventWriter<MoveEvent>,
+    mut blocked_writer: EventWriter<BlockedMoveEvent>,
+    mut win_writer: EventWriter<WinEvent>,
+    mut history: ResMut<History>,
+    settings: Res<Settings>,
+    mut game: ResMut<Game>,
+    mut run_state: ResMut<State<RunState>>,
+) {
+    let touch_event = touches.iter_just_released().next();
+    let requested = move_requests.iter().last().map(|request| request.0);
+    let topology = grid.topology();
+    let direction = key_direction(&keyboard_input, topology)
+        .or_else(|| touch_event.and_then(|t| swipe_direction(topology, t.distance())))
+        .or(requested);
+
+    if let Some(direction) = direction {
+        let before = Snapshot::take(&grid, &game_rng, &game);
+        match grid.play_with_spawns(
+            direction,
+            &spawn_table,
+            settings.spawns_per_move,
+            &mut *game_rng,
+        ) {
+            Some(outcome) => {
+                history.record(before);
+                game.score = game.score.saturating_add(outcome.score);
+                game.moves += 1;
+                move_writer.send(MoveEvent(outcome));
+
+                let game_over = !grid.has_moves();
+                if !game.won && grid.ruleset().has_won(&grid, settings.win_level) {
+                    game.won = true;
+                    win_writer.send(WinEvent {
+                        level: settings.win_level,
+                        score: game.score,
+                    });
+                    if !game_over {
+                        change_run_state(run_state.push(RunState::Won));
                     }
                 }
-            }
-            break;
-        }
-        // insert new block
-        tile_writer.send(NewTileEvent);
-    } else if move_up {
-        let mut it = blocks
-            .iter_mut()
-            .sorted_by(|a, b| match Ord::cmp(&b.1.x, &a.1.x) {
-                std::cmp::Ordering::Equal => Ord::cmp(&b.1.y, &a.1.y),
-                ordering => ordering,
-            })
-            .peekable();
-        let mut y: u8 = 0;
-
-        loop {
-            match (it.next(), it.peek()) {
-                (None, _) => {
-                    // nothing left to process
-                    break;
-                }
-                (Some(mut block), None) => {
-                    block.1.y = board.size - 1 - y;
-                }
-                (Some(mut block), Some(block_next)) => {
-                    match should_merge(
-                        (block.2.level, block.1.x),
-                        (block_next.2.level, block_next.1.x),
-                    ) {
-                        MergeStatus::Merge => {
-                            // despawn the next block, and
-                            // merge it with the current
-                            // block.
-                            let real_next_block = it
-                                .next()
-                                .expect("A peeked block should always exist when we .next here");
-                            block.2.level += 1;
-                            block.1.y = board.size - 1 - y;
-
-                            // update score
-                            game.score += block.2.score();
-                            // update text
-                            for child in block.3.iter() {
-                                let mut text = texts.get_mut(*child).expect("text to exist");
-                                let mut section = text
-                                    .1
-                                    .sections
-                                    .first_mut()
-                                    .expect("expect a single section in text");
-                                section.value = block.2.score().to_string();
-                            }
-                            // if the next, next block
-                            // (block #3 of 3)
-                            // isn't in the same row, reset
-                            // x
-                            // otherwise increment by one
-                            if let Some(future) = it.peek() {
-                                if block.1.x != future.1.x {
-                                    y = 0;
-                                } else {
-                                    y += 1;
-                                }
-                            }
-                            commands.entity(real_next_block.0).despawn_recursive();
-                            continue;
-                        }
-                        MergeStatus::DifferentRows => {
-                            block.1.y = board.size - 1 - y;
-                            y = 0;
-                            continue;
-                        }
-                        MergeStatus::DifferentValues => {
-                            block.1.y = board.size - 1 - y;
-                            y += 1;
-                            continue;
-                        }
-                    }
+                if game_over {
+                    change_run_state(run_state.push(RunState::GameOver));
                 }
             }
-            break;
+            None => blocked_writer.send(BlockedMoveEvent(direction)),
         }
-        // insert new block
-        tile_writer.send(NewTileEvent);
     }
     if game.score_best < game.score {
         game.score_best = game.score;
     }
 }
 
-fn new_tile_handler(
-    mut tile_reader: EventReader<NewTileEvent>,
-    mut commands: Commands,
-    query_board: Query<&Board>,
-    asset_server: Res<AssetServer>,
-    blocks: Query<(&Position, &Block)>,
-) {
-    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-    let board = query_board.single();
-
-    if tile_reader.iter().next().is_some() {
-        // insert new tile
-        let mut rng = rand::thread_rng();
-        let possible_position = (0..board.size)
-            .cartesian_product(0..board.size)
-            .filter(|tile_pos| {
-                !blocks
-                    .iter()
-                    .any(|(pos, _)| pos.x == tile_pos.0 && pos.y == tile_pos.1)
-            })
-            .choose(&mut rng);
-
-        if let Some((x, y)) = possible_position {
-            let pos = Position { x, y };
-            let new_block = Block::default();
-            commands
-                .spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
-                        color: new_block.color(),
-                        ..default()
-                    },
-                    transform: Transform::from_xyz(
-                        block_pos_to_transform(board.size, pos.x),
-                        block_pos_to_transform(board.size, pos.y),
-                        1.0,
-                    ),
-                    ..default()
-                })
-                .with_children(|child_builder| {
-                    child_builder
-                        .spawn_bundle(Text2dBundle {
-                            text: Text::with_section(
-                                new_block.score().to_string(),
-                                TextStyle {
-                                    font: font.clone(),
-                                    font_size: 40.0,
-                                    color: Color::BLACK,
-                                },
-                                TextAlignment {
-                                    vertical: VerticalAlign::Center,
-                                    horizontal: HorizontalAlign::Center,
-                                },
-                            ),
-                            transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                            ..default()
-                        })
-                        .insert(BlockText);
-                })
-                .insert(new_block)
-                .insert(pos);
-        };
-    };
+// several systems change the run state, e.g. a move ending the game in the
+// frame the End Game button is clicked: the first change queued wins and the
+// others are dropped
+fn change_run_state(change: Result<(), StateError>) {
+    match change {
+        Ok(()) | Err(StateError::StateAlreadyQueued) => {}
+        Err(error) => panic!("invalid run state change: {:?}", error),
+    }
 }
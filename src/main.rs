@@ -1,3 +1,80 @@
 fn main() {
-    yars2048::main();
+    let mut settings = yars2048::Settings::default();
+    let mut jokers = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let seed = args.next().and_then(|seed| seed.parse().ok());
+                settings.seed = Some(seed.expect("--seed expects a number"));
+            }
+            "--size" => {
+                let size = args.next().and_then(|size| match size.split_once('x') {
+                    Some((width, height)) => width.parse().ok().zip(height.parse().ok()),
+                    None => size.parse().ok().map(|side| (side, side)),
+                });
+                (settings.board_width, settings.board_height) =
+                    size.expect("--size expects a size like 4x4 or 5");
+            }
+            "--torus" => settings.topology = yars2048::engine::Topology::Torus,
+            "--hex" => {
+                let side = args.next().and_then(|side| side.parse().ok());
+                settings.topology = yars2048::engine::Topology::Hex;
+                settings.board_width = side.expect("--hex expects the number of tiles on a side");
+            }
+            "--rules" => {
+                let name = args.next().expect("--rules expects a rule set");
+                let rules = yars2048::engine::Rules::from_name(&name)
+                    .unwrap_or_else(|| panic!("unknown rule set {}", name));
+                settings = settings.with_ruleset(rules);
+            }
+            "--jokers" => {
+                let weight = args.next().and_then(|weight| weight.parse().ok());
+                jokers = Some(weight.expect("--jokers expects a spawn weight"));
+            }
+            "--wall" => {
+                let wall = args.next().and_then(|wall| {
+                    let (x, y) = wall.split_once(',')?;
+                    Some(yars2048::engine::Cell {
+                        x: x.parse().ok()?,
+                        y: y.parse().ok()?,
+                    })
+                });
+                settings
+                    .walls
+                    .push(wall.expect("--wall expects a cell like 1,2"));
+            }
+            "--opening-tiles" => {
+                let count = args.next().and_then(|count| count.parse().ok());
+                settings.opening_tiles = count.expect("--opening-tiles expects a number");
+            }
+            "--spawns" => {
+                let count = args.next().and_then(|count| count.parse().ok());
+                settings.spawns_per_move = count.expect("--spawns expects a number of tiles");
+            }
+            "--bot" => {
+                let name = args.next().expect("--bot expects a strategy");
+                settings.autoplay_strategy = match name.as_str() {
+                    // the default, which expects the spawns of --spawns
+                    "expectimax" => None,
+                    name => {
+                        let strategy = yars2048::engine::strategy_from_name(name)
+                            .unwrap_or_else(|| panic!("unknown strategy {}", name));
+                        Some(strategy.into())
+                    }
+                };
+            }
+            "--load" => {
+                settings.load_code = Some(args.next().expect("--load expects a share code"));
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    // after --rules, which replaces the spawn table
+    if let Some(weight) = jokers {
+        settings.spawn_table = settings.spawn_table.with_jokers(weight);
+    }
+
+    yars2048::run(settings);
 }
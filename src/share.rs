@@ -0,0 +1,235 @@
+use bevy::ecs::event::{Events, ManualEventReader};
+use bevy::prelude::*;
+
+use crate::components::{Block, Board, Game, RunState, Settings, ShareCodeDisplay};
+use crate::engine::{GameRng, Grid, SharedGame, Topology};
+use crate::history::History;
+use crate::{change_run_state, redraw_blocks, spawn_board_sprite, tile_texture, Palette};
+
+/// Shows the share code of the running game.
+pub struct ShareEvent;
+
+/// Loads the game of a share code in place of the running one.
+pub struct LoadCodeEvent(pub String);
+
+/// A key of the on-screen keypad typing share codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub enum CodeKey {
+    Character(char),
+    Delete,
+    /// Loads the code typed so far.
+    Load,
+}
+
+/// A key of the on-screen keypad was clicked.
+pub struct CodeKeyEvent(pub CodeKey);
+
+/// The share code being typed in [`RunState::EnteringCode`].
+#[derive(Default)]
+struct CodeEntry {
+    code: String,
+    characters: ManualEventReader<ReceivedCharacter>,
+}
+
+pub struct SharePlugin;
+
+impl Plugin for SharePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CodeEntry>()
+            .add_event::<ShareEvent>()
+            .add_event::<LoadCodeEvent>()
+            .add_event::<CodeKeyEvent>()
+            .add_startup_system(load_settings_code)
+            .add_system_set(SystemSet::on_update(RunState::Playing).with_system(share_input))
+            .add_system_set(
+                SystemSet::on_enter(RunState::EnteringCode).with_system(start_code_entry),
+            )
+            .add_system_set(SystemSet::on_update(RunState::EnteringCode).with_system(code_entry))
+            .add_system_set(SystemSet::on_exit(RunState::EnteringCode).with_system(clear_display))
+            .add_system(show_share_code)
+            // after the first game has been set up, which it replaces
+            .add_system_to_stage(CoreStage::PostUpdate, load_code);
+    }
+}
+
+fn load_settings_code(settings: Res<Settings>, mut load_writer: EventWriter<LoadCodeEvent>) {
+    if let Some(code) = &settings.load_code {
+        load_writer.send(LoadCodeEvent(code.clone()));
+    }
+}
+
+fn share_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut share_writer: EventWriter<ShareEvent>,
+    mut run_state: ResMut<State<RunState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::C) {
+        share_writer.send(ShareEvent);
+    } else if keyboard_input.just_pressed(KeyCode::L) {
+        change_run_state(run_state.push(RunState::EnteringCode));
+    }
+}
+
+fn show_share_code(
+    mut share_reader: EventReader<ShareEvent>,
+    grid: Res<Grid>,
+    game_rng: Res<GameRng>,
+    game: Res<Game>,
+    mut query_display: Query<&mut Text, With<ShareCodeDisplay>>,
+) {
+    if share_reader.iter().count() == 0 {
+        return;
+    }
+    let code = SharedGame {
+        grid: grid.clone(),
+        rng: *game_rng,
+        score: game.score,
+        moves: game.moves,
+    }
+    .encode();
+    info!("share code: {}", code);
+    query_display.single_mut().sections[0].value = code;
+}
+
+fn start_code_entry(
+    mut entry: ResMut<CodeEntry>,
+    characters: Res<Events<ReceivedCharacter>>,
+    mut query_display: Query<&mut Text, With<ShareCodeDisplay>>,
+) {
+    entry.code.clear();
+    // skips the key that opened the entry
+    entry.characters.iter(&characters).for_each(drop);
+    query_display.single_mut().sections[0].value = "Code: _".to_string();
+}
+
+// characters typed on the keyboard or the on-screen keypad go into the code,
+// enter loads it and escape goes back to the game
+fn code_entry(
+    keyboard_input: Res<Input<KeyCode>>,
+    characters: Res<Events<ReceivedCharacter>>,
+    mut key_reader: EventReader<CodeKeyEvent>,
+    mut entry: ResMut<CodeEntry>,
+    mut run_state: ResMut<State<RunState>>,
+    mut load_writer: EventWriter<LoadCodeEvent>,
+    mut query_display: Query<&mut Text, With<ShareCodeDisplay>>,
+) {
+    let entry = &mut *entry;
+    let mut load = keyboard_input.just_pressed(KeyCode::Return);
+    for received in entry.characters.iter(&characters) {
+        if !received.char.is_control() {
+            entry.code.push(received.char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        entry.code.pop();
+    }
+    for CodeKeyEvent(key) in key_reader.iter() {
+        match key {
+            CodeKey::Character(c) => entry.code.push(*c),
+            CodeKey::Delete => {
+                entry.code.pop();
+            }
+            CodeKey::Load => load = true,
+        }
+    }
+
+    if load {
+        load_writer.send(LoadCodeEvent(std::mem::take(&mut entry.code)));
+        change_run_state(run_state.pop());
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        change_run_state(run_state.pop());
+    } else {
+        query_display.single_mut().sections[0].value = format!("Code: {}_", entry.code);
+    }
+}
+
+fn clear_display(mut query_display: Query<&mut Text, With<ShareCodeDisplay>>) {
+    query_display.single_mut().sections[0].value.clear();
+}
+
+// whether the app can show a board like `grid`
+fn is_supported(grid: &Grid) -> bool {
+    match grid.topology() {
+        Topology::Square | Topology::Torus => {
+            Settings::BOARD_SIZES.contains(&grid.width())
+                && Settings::BOARD_SIZES.contains(&grid.height())
+        }
+        Topology::Hex => Settings::HEX_SIDES.contains(&(grid.width() / 2 + 1)),
+    }
+}
+
+fn load_code(
+    mut commands: Commands,
+    mut load_reader: EventReader<LoadCodeEvent>,
+    mut grid: ResMut<Grid>,
+    mut game_rng: ResMut<GameRng>,
+    mut game: ResMut<Game>,
+    mut history: ResMut<History>,
+    mut run_state: ResMut<State<RunState>>,
+    settings: Res<Settings>,
+    palette: Res<Palette>,
+    time: Res<Time>,
+    blocks: Query<Entity, With<Block>>,
+    query_board: Query<(Entity, &Board)>,
+    mut query_display: Query<&mut Text, With<ShareCodeDisplay>>,
+    asset_server: Res<AssetServer>,
+) {
+    let code = match load_reader.iter().last() {
+        Some(LoadCodeEvent(code)) => code,
+        None => return,
+    };
+    if run_state.current() != &RunState::Playing {
+        return;
+    }
+    let (board_entity, board) = query_board.single();
+    let shared = match SharedGame::decode_with_ruleset(code, board.ruleset.clone()) {
+        Ok(shared) if is_supported(&shared.grid) => shared,
+        Ok(_) => {
+            query_display.single_mut().sections[0].value = "Unsupported board size".to_string();
+            return;
+        }
+        Err(error) => {
+            query_display.single_mut().sections[0].value = format!("Invalid code: {}", error);
+            return;
+        }
+    };
+
+    let loaded = shared.grid;
+    let mut new_board = None;
+    if loaded.topology() != board.topology
+        || loaded.width() != board.width
+        || loaded.height() != board.height
+        || !loaded.walls().eq(board.walls.iter().copied())
+    {
+        commands.entity(board_entity).despawn_recursive();
+        new_board = Some(Board {
+            width: loaded.width(),
+            height: loaded.height(),
+            walls: loaded.walls().collect(),
+            ruleset: board.ruleset.clone(),
+            topology: loaded.topology(),
+            tile_texture: tile_texture(&asset_server, loaded.topology()),
+        });
+    }
+    let board = new_board.as_ref().unwrap_or(board);
+
+    history.clear();
+    *grid = loaded;
+    *game_rng = shared.rng;
+    game.score = shared.score;
+    game.score_best = game.score_best.max(shared.score);
+    game.moves = shared.moves;
+    game.hints = 0;
+    game.won = grid.ruleset().has_won(&grid, settings.win_level);
+    game.started = time.seconds_since_startup();
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    redraw_blocks(&mut commands, &font, board, &grid, blocks.iter());
+    if let Some(board) = new_board {
+        spawn_board_sprite(&mut commands, &palette, &asset_server, board);
+    }
+    query_display.single_mut().sections[0].value.clear();
+
+    if !grid.has_moves() {
+        change_run_state(run_state.push(RunState::GameOver));
+    }
+}
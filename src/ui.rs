@@ -1,12 +1,24 @@
 mod buttons;
+mod keypad;
 
 use bevy::prelude::*;
 
-use crate::components::{BestScoreDisplay, Game, ScoreDisplay};
-use buttons::{button_system, ButtonColors};
+use crate::components::{
+    BestScoreDisplay, Game, RunState, ScoreDisplay, SeedDisplay, ShareCodeDisplay,
+};
+use crate::engine::GameRng;
+use buttons::{button_label_system, button_system, ButtonAction, ButtonColors};
+use keypad::{despawn_keypad, keypad_system, spawn_keypad};
 
 const BACKGROUND_COLOR: Color = Color::rgb(0.73, 0.68, 0.63);
 
+/// Size of the buttons below the board, in pixels, small enough for three
+/// of them to fit across a phone.
+const BUTTON_WIDTH: f32 = 100.0;
+const BUTTON_HEIGHT: f32 = 44.0;
+const BUTTON_MARGIN: f32 = 4.0;
+const BUTTONS_PER_ROW: f32 = 3.0;
+
 pub struct GameUiPlugin;
 
 impl Plugin for GameUiPlugin {
@@ -14,7 +26,12 @@ impl Plugin for GameUiPlugin {
         app.add_startup_system(setup_ui)
             .init_resource::<ButtonColors>()
             .add_system(button_system)
-            .add_system(scoreboard);
+            .add_system(button_label_system)
+            .add_system_set(SystemSet::on_enter(RunState::EnteringCode).with_system(spawn_keypad))
+            .add_system_set(SystemSet::on_update(RunState::EnteringCode).with_system(keypad_system))
+            .add_system_set(SystemSet::on_exit(RunState::EnteringCode).with_system(despawn_keypad))
+            .add_system(scoreboard)
+            .add_system(seed_display);
     }
 }
 
@@ -173,43 +190,108 @@ fn setup_ui(
                     // end best scorebox
                 });
             parent
-                .spawn_bundle(ButtonBundle {
+                .spawn_bundle(NodeBundle {
                     style: Style {
-                        size: Size::new(Val::Px(100.0), Val::Px(60.0)),
-                        // center button
-                        // margin: Rect::all(Val::Auto),
-                        // horizontally center child text
+                        display: Display::Flex,
                         justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        margin: Rect {
-                            left: Val::Px(20.0),
-                            right: Val::Px(20.0),
-                            top: Val::Px(20.0),
-                            bottom: Val::Px(20.0),
-                        },
+                        // first row on top, the y axis of the UI points up
+                        flex_wrap: FlexWrap::WrapReverse,
+                        max_size: Size::new(
+                            Val::Px(BUTTONS_PER_ROW * (BUTTON_WIDTH + 2.0 * BUTTON_MARGIN)),
+                            Val::Undefined,
+                        ),
                         ..default()
                     },
-                    color: button_colors.normal.into(),
+                    color: Color::NONE.into(),
                     ..default()
                 })
                 .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Button",
-                            TextStyle {
-                                font: font.clone(),
-                                font_size: 20.0,
-                                color: Color::WHITE,
-                            },
-                            default(),
-                        ),
-                        ..default()
-                    });
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Undo);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Game);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Redo);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Share);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Load);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Hint);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::AutoplaySlower);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::Autoplay);
+                    spawn_button(parent, &font, &button_colors, ButtonAction::AutoplayFaster);
                 });
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "<seed>",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    ..default()
+                })
+                .insert(SeedDisplay);
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    ..default()
+                })
+                .insert(ShareCodeDisplay);
         });
 }
 
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    button_colors: &ButtonColors,
+    action: ButtonAction,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(BUTTON_WIDTH), Val::Px(BUTTON_HEIGHT)),
+                // center button
+                // margin: Rect::all(Val::Auto),
+                // horizontally center child text
+                justify_content: JustifyContent::Center,
+                // vertically center child text
+                align_items: AlignItems::Center,
+                margin: Rect::all(Val::Px(BUTTON_MARGIN)),
+                ..default()
+            },
+            color: button_colors.normal.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Button",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+        })
+        .insert(action);
+}
+
 // update the score displayed during the game
 fn scoreboard(
     game: Res<Game>,
@@ -226,3 +308,9 @@ fn scoreboard(
     let mut best_text = p1.single_mut();
     best_text.sections[0].value = game.score_best.to_string();
 }
+
+// show the seed of the running game so it can be replayed
+fn seed_display(game_rng: Res<GameRng>, mut query_seed: Query<&mut Text, With<SeedDisplay>>) {
+    let mut text = query_seed.single_mut();
+    text.sections[0].value = format!("Seed {}", game_rng.seed());
+}
@@ -1,6 +1,51 @@
 use bevy::prelude::*;
 
+use crate::autoplay::{AutoplaySpeedEvent, ToggleAutoplayEvent};
+use crate::change_run_state;
 use crate::components::RunState;
+use crate::hint::HintEvent;
+use crate::history::{RedoEvent, UndoEvent};
+use crate::share::ShareEvent;
+
+/// What a button does when it is clicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub enum ButtonAction {
+    /// Ends, restarts or resumes the game, depending on the run state.
+    Game,
+    Undo,
+    Redo,
+    /// Shows the share code of the game.
+    Share,
+    /// Opens the keypad to type a share code.
+    Load,
+    /// Shows the best move.
+    Hint,
+    /// Starts or stops autoplay.
+    Autoplay,
+    AutoplaySlower,
+    AutoplayFaster,
+}
+
+impl ButtonAction {
+    fn label(&self, run_state: &RunState) -> &'static str {
+        match self {
+            ButtonAction::Game => match run_state {
+                RunState::Playing => "End Game",
+                RunState::Won => "Keep Going",
+                RunState::GameOver => "New Game",
+                RunState::EnteringCode => "Cancel",
+            },
+            ButtonAction::Undo => "Undo",
+            ButtonAction::Redo => "Redo",
+            ButtonAction::Share => "Share",
+            ButtonAction::Load => "Load",
+            ButtonAction::Hint => "Hint",
+            ButtonAction::Autoplay => "Auto",
+            ButtonAction::AutoplaySlower => "Slower",
+            ButtonAction::AutoplayFaster => "Faster",
+        }
+    }
+}
 
 pub struct ButtonColors {
     pub normal: Color,
@@ -21,13 +66,19 @@ impl FromWorld for ButtonColors {
 pub fn button_system(
     button_colors: Res<ButtonColors>,
     mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &Children),
+        (&Interaction, &mut UiColor, &Children, &ButtonAction),
         (Changed<Interaction>, With<Button>),
     >,
     mut text_query: Query<&mut Text>,
     mut run_state: ResMut<State<RunState>>,
+    mut undo_writer: EventWriter<UndoEvent>,
+    mut redo_writer: EventWriter<RedoEvent>,
+    mut share_writer: EventWriter<ShareEvent>,
+    mut hint_writer: EventWriter<HintEvent>,
+    mut autoplay_writer: EventWriter<ToggleAutoplayEvent>,
+    mut speed_writer: EventWriter<AutoplaySpeedEvent>,
 ) {
-    for (interaction, mut color, children) in interaction_query.iter_mut() {
+    for (interaction, mut color, children, action) in interaction_query.iter_mut() {
         let mut text = text_query
             .get_mut(
                 *children
@@ -39,30 +90,63 @@ pub fn button_system(
             Interaction::Clicked => {
                 *color = button_colors.pressed.into();
 
-                match run_state.current() {
-                    RunState::Playing => {
-                        run_state.set(RunState::GameOver).unwrap();
-                    }
-                    RunState::GameOver => {
-                        run_state.set(RunState::Playing).unwrap();
+                match action {
+                    ButtonAction::Game => match run_state.current() {
+                        RunState::Playing => {
+                            change_run_state(run_state.push(RunState::GameOver));
+                        }
+                        RunState::Won | RunState::EnteringCode => {
+                            // keep going, or stop typing a code
+                            change_run_state(run_state.pop());
+                        }
+                        RunState::GameOver => {
+                            change_run_state(run_state.replace(RunState::Playing));
+                        }
+                    },
+                    ButtonAction::Undo => undo_writer.send(UndoEvent),
+                    ButtonAction::Redo => redo_writer.send(RedoEvent),
+                    ButtonAction::Share => share_writer.send(ShareEvent),
+                    ButtonAction::Load => {
+                        if run_state.current() == &RunState::Playing {
+                            change_run_state(run_state.push(RunState::EnteringCode));
+                        }
                     }
+                    ButtonAction::Hint => hint_writer.send(HintEvent),
+                    ButtonAction::Autoplay => autoplay_writer.send(ToggleAutoplayEvent),
+                    ButtonAction::AutoplaySlower => speed_writer.send(AutoplaySpeedEvent::Slower),
+                    ButtonAction::AutoplayFaster => speed_writer.send(AutoplaySpeedEvent::Faster),
                 }
             }
             Interaction::Hovered => {
                 *color = button_colors.hovered.into();
             }
             Interaction::None => {
-                match run_state.current() {
-                    RunState::Playing => {
-                        text.sections[0].value = "End Game".to_string();
-                    }
-                    RunState::GameOver => {
-                        text.sections[0].value = "New Game".to_string();
-                    }
-                }
+                text.sections[0].value = action.label(run_state.current()).to_string();
 
                 *color = button_colors.normal.into();
             }
         }
     }
 }
+
+// the state can also change without touching the button,
+// e.g. when a move wins the game
+pub fn button_label_system(
+    run_state: Res<State<RunState>>,
+    buttons: Query<(&Children, &ButtonAction), With<Button>>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !run_state.is_changed() {
+        return;
+    }
+    for (children, action) in buttons.iter() {
+        let mut text = text_query
+            .get_mut(
+                *children
+                    .first()
+                    .expect("expect button to have a first child"),
+            )
+            .unwrap();
+        text.sections[0].value = action.label(run_state.current()).to_string();
+    }
+}
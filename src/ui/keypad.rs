@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use super::buttons::ButtonColors;
+use crate::engine::SharedGame;
+use crate::share::{CodeKey, CodeKeyEvent};
+
+/// Size of a character key, in pixels.
+const KEY_SIZE: f32 = 36.0;
+const KEY_MARGIN: f32 = 2.0;
+/// Character keys on a row of the keypad.
+const KEYS_PER_ROW: f32 = 8.0;
+
+/// The on-screen keypad shown while typing a share code, for screens
+/// without a keyboard.
+#[derive(Component)]
+struct Keypad;
+
+pub fn spawn_keypad(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_colors: Res<ButtonColors>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Keypad)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        // first row on top, the y axis of the UI points up
+                        flex_wrap: FlexWrap::WrapReverse,
+                        justify_content: JustifyContent::Center,
+                        size: Size::new(
+                            Val::Px(KEYS_PER_ROW * (KEY_SIZE + 2.0 * KEY_MARGIN)),
+                            Val::Undefined,
+                        ),
+                        ..default()
+                    },
+                    color: Color::rgba(0.47, 0.43, 0.4, 0.9).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for c in SharedGame::ALPHABET.iter() {
+                        spawn_key(
+                            parent,
+                            &font,
+                            &button_colors,
+                            CodeKey::Character(char::from(*c)),
+                        );
+                    }
+                    spawn_key(parent, &font, &button_colors, CodeKey::Delete);
+                    spawn_key(parent, &font, &button_colors, CodeKey::Load);
+                });
+        });
+}
+
+fn spawn_key(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    button_colors: &ButtonColors,
+    key: CodeKey,
+) {
+    let (label, width) = match key {
+        CodeKey::Character(c) => (c.to_string(), KEY_SIZE),
+        // the two of them fill a row
+        CodeKey::Delete => ("Delete".to_string(), 4.0 * KEY_SIZE + 6.0 * KEY_MARGIN),
+        CodeKey::Load => ("Load".to_string(), 4.0 * KEY_SIZE + 6.0 * KEY_MARGIN),
+    };
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(KEY_SIZE)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                margin: Rect::all(Val::Px(KEY_MARGIN)),
+                ..default()
+            },
+            color: button_colors.normal.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+        })
+        .insert(key);
+}
+
+pub fn despawn_keypad(mut commands: Commands, keypads: Query<Entity, With<Keypad>>) {
+    for entity in keypads.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn keypad_system(
+    button_colors: Res<ButtonColors>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &CodeKey),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut key_writer: EventWriter<CodeKeyEvent>,
+) {
+    for (interaction, mut color, key) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *color = button_colors.pressed.into();
+                key_writer.send(CodeKeyEvent(*key));
+            }
+            Interaction::Hovered => *color = button_colors.hovered.into(),
+            Interaction::None => *color = button_colors.normal.into(),
+        }
+    }
+}